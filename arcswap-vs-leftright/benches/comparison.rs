@@ -1,7 +1,13 @@
-use arcswap_vs_leftright::{
-    ArcSwapVersion, LeftRightVersion, MutexVersion, RwLockVersion, ValueManipulator,
+#[cfg(feature = "backend-epoch")]
+use arcswap_vs_leftright::cell::EpochCell;
+use arcswap_vs_leftright::cell::{
+    self, ArcSwapCell, LeftRightCell, MutexCell, RwLockCell, SharedCell,
+};
+use arcswap_vs_leftright::ValueManipulator;
+use criterion::measurement::WallTime;
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion,
 };
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 
 fn bench_with_few_writes<V: ValueManipulator + 'static>(
     getter: V,
@@ -16,7 +22,7 @@ fn bench_with_few_writes<V: ValueManipulator + 'static>(
             scope.spawn(move || {
                 for i in 0..num_iterations {
                     if i % write_modulo == 0 {
-                        black_box(getter.set_value(i));
+                        getter.set_value(black_box(i));
                     } else {
                         black_box(getter.get_value());
                     }
@@ -43,6 +49,28 @@ fn bench_only_reads<V: ValueManipulator + 'static>(
     });
 }
 
+fn bench_backend<V>(group: &mut BenchmarkGroup<'_, WallTime>, name: &str, num_iterations: u64)
+where
+    V: SharedCell<u64> + 'static,
+{
+    group.bench_with_input(
+        BenchmarkId::new(format!("{}-read", name), num_iterations),
+        &num_iterations,
+        |b, &num_iterations| {
+            b.iter(|| bench_only_reads(V::new(0), num_iterations, 3));
+        },
+    );
+
+    // read + 1% writes
+    group.bench_with_input(
+        BenchmarkId::new(format!("{}-rw", name), num_iterations),
+        &num_iterations,
+        |b, &num_iterations| {
+            b.iter(|| bench_with_few_writes(V::new(0), num_iterations, 3));
+        },
+    );
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     let parallelisms = vec![1, 3, 10];
 
@@ -52,72 +80,14 @@ fn criterion_benchmark(c: &mut Criterion) {
         let iteration_counts: Vec<u64> = vec![10_000, 100_000, 500_000, 1_000_000];
 
         for num_iterations in iteration_counts {
-            group.bench_with_input(
-                BenchmarkId::new("Mutex-read", num_iterations),
-                &num_iterations,
-                |b, &num_iterations| {
-                    b.iter(|| bench_only_reads(MutexVersion::default(), num_iterations, 3));
-                },
-            );
-
-            group.bench_with_input(
-                BenchmarkId::new("RwLock-read", num_iterations),
-                &num_iterations,
-                |b, &num_iterations| {
-                    b.iter(|| bench_only_reads(RwLockVersion::default(), num_iterations, 3));
-                },
-            );
-
-            group.bench_with_input(
-                BenchmarkId::new("ArcSwap-read", num_iterations),
-                &num_iterations,
-                |b, &num_iterations| {
-                    b.iter(|| bench_only_reads(ArcSwapVersion::default(), num_iterations, 3));
-                },
-            );
-
-            group.bench_with_input(
-                BenchmarkId::new("Left-Right-read", num_iterations),
-                &num_iterations,
-                |b, &num_iterations| {
-                    b.iter(|| bench_only_reads(LeftRightVersion::default(), num_iterations, 3));
-                },
-            );
-
-            // read + 1% writes
-            group.bench_with_input(
-                BenchmarkId::new("Mutex-rw", num_iterations),
-                &num_iterations,
-                |b, &num_iterations| {
-                    b.iter(|| bench_with_few_writes(MutexVersion::default(), num_iterations, 3));
-                },
-            );
-
-            group.bench_with_input(
-                BenchmarkId::new("RwLock-rw", num_iterations),
-                &num_iterations,
-                |b, &num_iterations| {
-                    b.iter(|| bench_with_few_writes(RwLockVersion::default(), num_iterations, 3));
-                },
-            );
-
-            group.bench_with_input(
-                BenchmarkId::new("ArcSwap-rw", num_iterations),
-                &num_iterations,
-                |b, &num_iterations| {
-                    b.iter(|| bench_with_few_writes(ArcSwapVersion::default(), num_iterations, 3));
-                },
-            );
-
-            group.bench_with_input(
-                BenchmarkId::new("Left-Right-rw", num_iterations),
-                &num_iterations,
-                |b, &num_iterations| {
-                    b.iter(|| {
-                        bench_with_few_writes(LeftRightVersion::default(), num_iterations, 3)
-                    });
-                },
-            );
+            bench_backend::<MutexCell<u64>>(&mut group, "Mutex", num_iterations);
+            bench_backend::<RwLockCell<u64>>(&mut group, "RwLock", num_iterations);
+            bench_backend::<ArcSwapCell<u64>>(&mut group, "ArcSwap", num_iterations);
+            bench_backend::<LeftRightCell<u64>>(&mut group, "Left-Right", num_iterations);
+            #[cfg(feature = "backend-epoch")]
+            bench_backend::<EpochCell<u64>>(&mut group, "Epoch", num_iterations);
+            // the backend services get with the enabled `backend-*` feature
+            bench_backend::<cell::Cell<u64>>(&mut group, "Cell", num_iterations);
         }
         group.finish();
     }
@@ -0,0 +1,558 @@
+//! Shared value with interchangeable synchronization backends.
+//!
+//! Every backend implements [`SharedCell`], so services can write against the trait, or against
+//! the [`Cell`] alias, and pick the backend, that fits their measured workload, by a cargo
+//! feature: `backend-left-right`, `backend-rwlock`, `backend-mutex` or `backend-epoch`.
+//! Without any of them [`Cell`] is backed by `ArcSwap`. Features are additive, so several
+//! of them can be enabled, e.g. by different crates of a workspace. Then the first one in
+//! the order above backs [`Cell`], while every backend stays available by its own name.
+
+use arc_swap::ArcSwap;
+use std::future;
+use std::marker::PhantomData;
+use std::mem;
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+use crate::left_right_cell;
+
+#[cfg(feature = "backend-left-right")]
+pub type Cell<T> = LeftRightCell<T>;
+#[cfg(all(feature = "backend-rwlock", not(feature = "backend-left-right")))]
+pub type Cell<T> = RwLockCell<T>;
+#[cfg(all(
+    feature = "backend-mutex",
+    not(any(feature = "backend-left-right", feature = "backend-rwlock"))
+))]
+pub type Cell<T> = MutexCell<T>;
+#[cfg(all(
+    feature = "backend-epoch",
+    not(any(
+        feature = "backend-left-right",
+        feature = "backend-rwlock",
+        feature = "backend-mutex"
+    ))
+))]
+pub type Cell<T> = EpochCell<T>;
+#[cfg(not(any(
+    feature = "backend-left-right",
+    feature = "backend-rwlock",
+    feature = "backend-mutex",
+    feature = "backend-epoch"
+)))]
+pub type Cell<T> = ArcSwapCell<T>;
+
+/// Value shared by clones of the cell. Clone the cell for every thread or task using it.
+pub trait SharedCell<T>: Clone + Send {
+    fn new(value: T) -> Self;
+
+    /// Returns a copy of the current value. Wrap large values in an `Arc` to keep this cheap.
+    fn load(&self) -> T;
+
+    fn store(&self, value: T);
+
+    /// Replaces the value with `f` applied to the current one and returns the previous value.
+    /// Some backends retry on concurrent stores, so `f` can be called more than once.
+    fn rcu<F>(&self, f: F) -> T
+    where
+        F: FnMut(&T) -> T;
+
+    /// Returns a subscription, that waits for stores done after this call.
+    fn subscribe(&self) -> Subscription<T, Self>;
+}
+
+/// Counts stores of a cell and wakes up subscriptions waiting for them.
+#[derive(Debug, Default)]
+struct Changes {
+    state: Mutex<ChangesState>,
+    condvar: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct ChangesState {
+    version: u64,
+    wakers: Vec<Waker>,
+}
+
+impl Changes {
+    fn version(&self) -> u64 {
+        self.state.lock().unwrap().version
+    }
+
+    /// Has to be called after the store, so subscriptions woken up load the new value.
+    fn changed(&self) {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            state.version += 1;
+            mem::take(&mut state.wakers)
+        };
+        self.condvar.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    fn poll_changed(&self, since: u64, cx: &mut Context<'_>) -> Poll<u64> {
+        let mut state = self.state.lock().unwrap();
+        if state.version > since {
+            return Poll::Ready(state.version);
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+
+    fn wait_changed(&self, since: u64, timeout: Duration) -> Option<u64> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.version > since {
+                return Some(state.version);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            state = self.condvar.wait_timeout(state, deadline - now).unwrap().0;
+        }
+    }
+}
+
+/// Waits for stores to a cell. The cell can not be dropped while subscribed,
+/// so unlike [`left_right_cell::ReadHandle::changed`] waiting never fails.
+pub struct Subscription<T, C> {
+    cell: C,
+    changes: Arc<Changes>,
+    /// Version of the last store this subscription has seen.
+    seen: u64,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T, C> Subscription<T, C>
+where
+    C: SharedCell<T>,
+{
+    fn new(cell: C, changes: Arc<Changes>) -> Self {
+        let seen = changes.version();
+        Self {
+            cell,
+            changes,
+            seen,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn load(&self) -> T {
+        self.cell.load()
+    }
+
+    /// Waits for a store after the one this subscription has seen last and marks it as seen.
+    /// Several stores in a row may be seen as a single change.
+    pub async fn changed(&mut self) -> u64 {
+        let since = self.seen;
+        let changes = &self.changes;
+        self.seen = future::poll_fn(|cx| changes.poll_changed(since, cx)).await;
+        self.seen
+    }
+
+    /// Blocking version of [`Subscription::changed`], returns `None` once `timeout` elapses.
+    pub fn wait_changed(&mut self, timeout: Duration) -> Option<u64> {
+        let version = self.changes.wait_changed(self.seen, timeout)?;
+        self.seen = version;
+        Some(version)
+    }
+}
+
+#[derive(Debug)]
+pub struct ArcSwapCell<T> {
+    inner: Arc<ArcSwap<T>>,
+    changes: Arc<Changes>,
+}
+
+impl<T> Clone for ArcSwapCell<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            changes: self.changes.clone(),
+        }
+    }
+}
+
+impl<T> SharedCell<T> for ArcSwapCell<T>
+where
+    T: Clone + Send + Sync,
+{
+    fn new(value: T) -> Self {
+        Self {
+            inner: Arc::new(ArcSwap::from_pointee(value)),
+            changes: Arc::default(),
+        }
+    }
+
+    fn load(&self) -> T {
+        T::clone(&self.inner.load())
+    }
+
+    fn store(&self, value: T) {
+        self.inner.store(Arc::new(value));
+        self.changes.changed();
+    }
+
+    fn rcu<F>(&self, mut f: F) -> T
+    where
+        F: FnMut(&T) -> T,
+    {
+        let previous = self.inner.rcu(|current| f(current));
+        self.changes.changed();
+        T::clone(&previous)
+    }
+
+    fn subscribe(&self) -> Subscription<T, Self> {
+        Subscription::new(self.clone(), self.changes.clone())
+    }
+}
+
+/// Writes are serialized by a mutex around the writer and published right away.
+pub struct LeftRightCell<T: Clone> {
+    writer: Arc<Mutex<left_right_cell::WriteHandle<T>>>,
+    reader: left_right_cell::ReadHandle<T>,
+    changes: Arc<Changes>,
+}
+
+impl<T: Clone> Clone for LeftRightCell<T> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+            reader: self.reader.clone(),
+            changes: self.changes.clone(),
+        }
+    }
+}
+
+impl<T> SharedCell<T> for LeftRightCell<T>
+where
+    T: Clone + Send + Sync,
+{
+    fn new(value: T) -> Self {
+        let (writer, reader) = left_right_cell::new(value);
+        Self {
+            writer: Arc::new(Mutex::new(writer)),
+            reader,
+            changes: Arc::default(),
+        }
+    }
+
+    fn load(&self) -> T {
+        T::clone(&self.reader.get().expect("writer is owned by the cell"))
+    }
+
+    fn store(&self, value: T) {
+        let mut writer = self.writer.lock().unwrap();
+        writer.set(value);
+        writer.publish();
+        drop(writer);
+        self.changes.changed();
+    }
+
+    fn rcu<F>(&self, mut f: F) -> T
+    where
+        F: FnMut(&T) -> T,
+    {
+        let mut writer = self.writer.lock().unwrap();
+        // every write is published under the lock, so the published value is the current one
+        let previous = self.load();
+        writer.set(f(&previous));
+        writer.publish();
+        drop(writer);
+        self.changes.changed();
+        previous
+    }
+
+    fn subscribe(&self) -> Subscription<T, Self> {
+        Subscription::new(self.clone(), self.changes.clone())
+    }
+}
+
+#[derive(Debug)]
+pub struct MutexCell<T> {
+    inner: Arc<Mutex<T>>,
+    changes: Arc<Changes>,
+}
+
+impl<T> Clone for MutexCell<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            changes: self.changes.clone(),
+        }
+    }
+}
+
+impl<T> SharedCell<T> for MutexCell<T>
+where
+    T: Clone + Send,
+{
+    fn new(value: T) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(value)),
+            changes: Arc::default(),
+        }
+    }
+
+    fn load(&self) -> T {
+        self.inner.lock().unwrap().clone()
+    }
+
+    fn store(&self, value: T) {
+        *self.inner.lock().unwrap() = value;
+        self.changes.changed();
+    }
+
+    fn rcu<F>(&self, mut f: F) -> T
+    where
+        F: FnMut(&T) -> T,
+    {
+        let mut value = self.inner.lock().unwrap();
+        let new = f(&value);
+        let previous = mem::replace(&mut *value, new);
+        drop(value);
+        self.changes.changed();
+        previous
+    }
+
+    fn subscribe(&self) -> Subscription<T, Self> {
+        Subscription::new(self.clone(), self.changes.clone())
+    }
+}
+
+#[derive(Debug)]
+pub struct RwLockCell<T> {
+    inner: Arc<RwLock<T>>,
+    changes: Arc<Changes>,
+}
+
+impl<T> Clone for RwLockCell<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            changes: self.changes.clone(),
+        }
+    }
+}
+
+impl<T> SharedCell<T> for RwLockCell<T>
+where
+    T: Clone + Send + Sync,
+{
+    fn new(value: T) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(value)),
+            changes: Arc::default(),
+        }
+    }
+
+    fn load(&self) -> T {
+        self.inner.read().unwrap().clone()
+    }
+
+    fn store(&self, value: T) {
+        *self.inner.write().unwrap() = value;
+        self.changes.changed();
+    }
+
+    fn rcu<F>(&self, mut f: F) -> T
+    where
+        F: FnMut(&T) -> T,
+    {
+        let mut value = self.inner.write().unwrap();
+        let new = f(&value);
+        let previous = mem::replace(&mut *value, new);
+        drop(value);
+        self.changes.changed();
+        previous
+    }
+
+    fn subscribe(&self) -> Subscription<T, Self> {
+        Subscription::new(self.clone(), self.changes.clone())
+    }
+}
+
+#[cfg(feature = "backend-epoch")]
+pub use epoch::EpochCell;
+
+#[cfg(feature = "backend-epoch")]
+mod epoch {
+    use super::{Changes, SharedCell, Subscription};
+    use crossbeam_epoch::{self as epoch, Atomic, Owned};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+
+    /// Atomic pointer to the value, replaced values are freed once no reader
+    /// can hold them anymore, tracked by epoch-based reclamation.
+    pub struct EpochCell<T> {
+        inner: Arc<Pointer<T>>,
+        changes: Arc<Changes>,
+    }
+
+    impl<T> Clone for EpochCell<T> {
+        fn clone(&self) -> Self {
+            Self {
+                inner: self.inner.clone(),
+                changes: self.changes.clone(),
+            }
+        }
+    }
+
+    struct Pointer<T>(Atomic<T>);
+
+    impl<T> Drop for Pointer<T> {
+        fn drop(&mut self) {
+            // SAFETY: the last clone of the cell is gone, so nobody else can access the value
+            unsafe {
+                let current = self.0.load(Ordering::Relaxed, epoch::unprotected());
+                drop(current.into_owned());
+            }
+        }
+    }
+
+    impl<T> SharedCell<T> for EpochCell<T>
+    where
+        T: Clone + Send + Sync,
+    {
+        fn new(value: T) -> Self {
+            Self {
+                inner: Arc::new(Pointer(Atomic::new(value))),
+                changes: Arc::default(),
+            }
+        }
+
+        fn load(&self) -> T {
+            let guard = epoch::pin();
+            let current = self.inner.0.load(Ordering::Acquire, &guard);
+            // SAFETY: the pointer is never null and the value is not freed while pinned
+            unsafe { current.deref() }.clone()
+        }
+
+        fn store(&self, value: T) {
+            let guard = epoch::pin();
+            let previous = self
+                .inner
+                .0
+                .swap(Owned::new(value), Ordering::AcqRel, &guard);
+            // SAFETY: the value is unreachable now, readers still holding it are pinned
+            unsafe { guard.defer_destroy(previous) };
+            drop(guard);
+            self.changes.changed();
+        }
+
+        fn rcu<F>(&self, mut f: F) -> T
+        where
+            F: FnMut(&T) -> T,
+        {
+            let guard = epoch::pin();
+            let mut current = self.inner.0.load(Ordering::Acquire, &guard);
+            loop {
+                // SAFETY: the pointer is never null and the value is not freed while pinned
+                let value = unsafe { current.deref() };
+                let new = Owned::new(f(value));
+                match self.inner.0.compare_exchange(
+                    current,
+                    new,
+                    Ordering::AcqRel,
+                    Ordering::Acquire,
+                    &guard,
+                ) {
+                    Ok(_) => {
+                        let previous = value.clone();
+                        // SAFETY: the value is unreachable now, readers still holding it are pinned
+                        unsafe { guard.defer_destroy(current) };
+                        drop(guard);
+                        self.changes.changed();
+                        return previous;
+                    }
+                    Err(err) => current = err.current,
+                }
+            }
+        }
+
+        fn subscribe(&self) -> Subscription<T, Self> {
+            Subscription::new(self.clone(), self.changes.clone())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn exercise<C: SharedCell<u64> + 'static>() {
+        let cell = C::new(1);
+        assert_eq!(cell.load(), 1);
+        cell.store(2);
+        assert_eq!(cell.rcu(|v| v * 10), 2);
+        assert_eq!(cell.load(), 20);
+
+        // concurrent read-modify-write does not lose updates
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let cell = cell.clone();
+                thread::spawn(move || {
+                    for _ in 0..100 {
+                        cell.rcu(|v| v + 1);
+                    }
+                })
+            })
+            .collect();
+        threads.into_iter().for_each(|t| t.join().unwrap());
+        assert_eq!(cell.load(), 420);
+
+        let mut subscription = cell.subscribe();
+        assert_eq!(subscription.wait_changed(Duration::ZERO), None);
+        let writer = cell.clone();
+        let t = thread::spawn(move || writer.store(7));
+        assert!(subscription.wait_changed(Duration::from_secs(10)).is_some());
+        assert_eq!(subscription.load(), 7);
+        t.join().unwrap();
+    }
+
+    #[test]
+    fn arc_swap() {
+        exercise::<ArcSwapCell<u64>>();
+    }
+
+    #[test]
+    fn left_right() {
+        exercise::<LeftRightCell<u64>>();
+    }
+
+    #[test]
+    fn mutex() {
+        exercise::<MutexCell<u64>>();
+    }
+
+    #[test]
+    fn rw_lock() {
+        exercise::<RwLockCell<u64>>();
+    }
+
+    #[cfg(feature = "backend-epoch")]
+    #[test]
+    fn epoch() {
+        exercise::<EpochCell<u64>>();
+    }
+
+    #[cfg(not(loom))]
+    #[tokio::test]
+    async fn changed() {
+        let cell = Cell::new(0u64);
+        let mut subscription = cell.subscribe();
+        let task = tokio::spawn(async move {
+            subscription.changed().await;
+            subscription.load()
+        });
+        tokio::task::yield_now().await;
+        cell.store(5);
+        assert_eq!(task.await.unwrap(), 5);
+    }
+}
@@ -1,19 +1,59 @@
+use std::fmt;
+use std::future;
+use std::mem;
 use std::ops::Deref;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
 
 use left_right::Absorb;
 
-struct SetOperation<T>(T);
+type Modifier<T> = Box<dyn FnOnce(&mut T) -> bool + Send>;
 
-impl<T> Absorb<SetOperation<T>> for Inner<T>
+enum Operation<T> {
+    Set(T),
+    /// Changes the value in place, returns whether it did change it.
+    Modify(Modifier<T>),
+    /// Operation, that did not change the first copy, so there is nothing to do for the second.
+    Noop,
+}
+
+impl<T> Absorb<Operation<T>> for Inner<T>
 where
     T: Clone,
 {
-    fn absorb_first(&mut self, operation: &mut SetOperation<T>, _: &Self) {
-        self.0 = operation.0.clone();
+    fn absorb_first(&mut self, operation: &mut Operation<T>, _: &Self) {
+        // closures are run only once, the second copy just takes the resulting value,
+        // so the value is cloned once per operation and closures need not be deterministic
+        *operation = match mem::replace(operation, Operation::Noop) {
+            Operation::Set(value) => {
+                self.0 = Some(value.clone());
+                Operation::Set(value)
+            }
+            Operation::Modify(modifier) => match &mut self.0 {
+                Some(value) => {
+                    if modifier(value) {
+                        Operation::Set(value.clone())
+                    } else {
+                        Operation::Noop
+                    }
+                }
+                None => Operation::Noop,
+            },
+            Operation::Noop => Operation::Noop,
+        };
     }
 
-    fn absorb_second(&mut self, operation: SetOperation<T>, _: &Self) {
-        self.0 = operation.0;
+    fn absorb_second(&mut self, operation: Operation<T>, _: &Self) {
+        match operation {
+            Operation::Set(value) => self.0 = Some(value),
+            Operation::Modify(modifier) => {
+                if let Some(value) = &mut self.0 {
+                    modifier(value);
+                }
+            }
+            Operation::Noop => {}
+        }
     }
 
     fn drop_first(self: Box<Self>) {}
@@ -23,71 +63,321 @@ where
     }
 }
 
+/// Value is `None` only until the first publish of a cell created by [`new_unpublished`].
 #[derive(Clone)]
-struct Inner<T>(T);
+struct Inner<T>(Option<T>);
 
-impl<T> Default for Inner<T>
-where
-    T: Default,
-{
-    fn default() -> Self {
-        Self(Default::default())
+/// Shared between the writer and readers.
+struct Shared<T> {
+    /// Value published last, stored by the writer before it is dropped.
+    last: OnceLock<T>,
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    /// Number of publishes so far.
+    version: u64,
+    closed: bool,
+    wakers: Vec<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn notify(&self, f: impl FnOnce(&mut State)) {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            f(&mut state);
+            mem::take(&mut state.wakers)
+        };
+        self.condvar.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    fn poll_changed(&self, since: u64, cx: &mut Context<'_>) -> Poll<Result<u64, Closed>> {
+        let mut state = self.state.lock().unwrap();
+        if state.version > since {
+            return Poll::Ready(Ok(state.version));
+        }
+        if state.closed {
+            return Poll::Ready(Err(Closed));
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
     }
 }
 
-#[derive(Clone)]
-pub struct ReadHandle<T>(left_right::ReadHandle<Inner<T>>);
+/// Error of waiting for a change, that never comes, because the writer is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Closed;
+
+impl fmt::Display for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("writer of the cell is gone")
+    }
+}
+
+impl std::error::Error for Closed {}
+
+pub struct ReadHandle<T> {
+    handle: left_right::ReadHandle<Inner<T>>,
+    shared: Arc<Shared<T>>,
+    /// Version the handle has seen a change of last.
+    seen: u64,
+}
+
+impl<T> Clone for ReadHandle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            handle: self.handle.clone(),
+            shared: self.shared.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+/// Result of [`ReadHandle::read`].
+pub enum Read<'a, T> {
+    /// Value currently published by the writer.
+    Value(ReadGuard<'a, T>),
+    /// Writer is gone, this is the value it published last.
+    Closed(ReadGuard<'a, T>),
+    /// Writer has not published any value yet, or is gone without publishing one.
+    NotPublished,
+}
+
+impl<'a, T> Read<'a, T> {
+    /// Returns the value, regardless of whether the writer is still alive.
+    pub fn into_value(self) -> Option<ReadGuard<'a, T>> {
+        match self {
+            Read::Value(guard) | Read::Closed(guard) => Some(guard),
+            Read::NotPublished => None,
+        }
+    }
+}
 
 impl<T> ReadHandle<T> {
-    pub fn get(&self) -> Option<ReadGuard<T>> {
-        self.0.enter().map(|guard| ReadGuard(guard))
+    /// Returns the published value, or the last published one once the writer is gone.
+    /// Only the writer being gone takes a slower path.
+    #[inline]
+    pub fn get(&self) -> Option<ReadGuard<'_, T>> {
+        self.read().into_value()
+    }
+
+    #[inline]
+    pub fn read(&self) -> Read<'_, T> {
+        match self.handle.enter() {
+            Some(guard) => match left_right::ReadGuard::try_map(guard, |inner| inner.0.as_ref()) {
+                Some(guard) => Read::Value(ReadGuard(Guard::Live(guard))),
+                None => Read::NotPublished,
+            },
+            None => self.read_closed(),
+        }
+    }
+
+    #[cold]
+    fn read_closed(&self) -> Read<'_, T> {
+        match self.shared.last.get() {
+            Some(value) => Read::Closed(ReadGuard(Guard::Closed(value))),
+            None => Read::NotPublished,
+        }
+    }
+
+    /// Returns true once the writer is dropped, so the value never changes again.
+    pub fn is_closed(&self) -> bool {
+        self.handle.was_dropped()
+    }
+
+    /// Number of publishes so far, publishing the pending operations of a dropped writer
+    /// included.
+    pub fn version(&self) -> u64 {
+        self.shared.state.lock().unwrap().version
     }
 
-    pub unsafe fn get_unchecked(&self) -> ReadGuard<T> {
-        self.0
-            .enter()
-            .map(|guard| ReadGuard(guard))
-            .unwrap_unchecked()
+    /// Waits for a publish after the one this handle has seen last and marks it as seen.
+    /// Returns the new version, or [`Closed`] if the writer is gone and no change is left to see.
+    pub async fn changed(&mut self) -> Result<u64, Closed> {
+        let since = self.seen;
+        let shared = &self.shared;
+        let version = future::poll_fn(|cx| shared.poll_changed(since, cx)).await?;
+        self.seen = version;
+        Ok(version)
+    }
+
+    /// Blocking version of [`ReadHandle::changed`], returns `Ok(None)` once `timeout` elapses.
+    pub fn wait_changed(&mut self, timeout: Duration) -> Result<Option<u64>, Closed> {
+        let deadline = Instant::now() + timeout;
+        let mut state = self.shared.state.lock().unwrap();
+        loop {
+            if state.version > self.seen {
+                self.seen = state.version;
+                return Ok(Some(state.version));
+            }
+            if state.closed {
+                return Err(Closed);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            state = self
+                .shared
+                .condvar
+                .wait_timeout(state, deadline - now)
+                .unwrap()
+                .0;
+        }
     }
 }
 
-pub struct ReadGuard<'a, T>(left_right::ReadGuard<'a, Inner<T>>);
+pub struct ReadGuard<'a, T>(Guard<'a, T>);
+
+enum Guard<'a, T> {
+    Live(left_right::ReadGuard<'a, T>),
+    Closed(&'a T),
+}
 
 impl<T> Deref for ReadGuard<'_, T> {
     type Target = T;
 
+    #[inline]
     fn deref(&self) -> &Self::Target {
-        &self.0.as_ref().0
+        match &self.0 {
+            Guard::Live(guard) => guard,
+            Guard::Closed(value) => value,
+        }
     }
 }
 
-pub struct WriteHandle<T: Clone>(left_right::WriteHandle<Inner<T>, SetOperation<T>>);
+pub struct WriteHandle<T: Clone> {
+    handle: left_right::WriteHandle<Inner<T>, Operation<T>>,
+    shared: Arc<Shared<T>>,
+}
 
 impl<T> WriteHandle<T>
 where
     T: Clone,
 {
     pub fn set(&mut self, value: T) {
-        self.0.append(SetOperation(value));
+        self.handle.append(Operation::Set(value));
+    }
+
+    /// Replaces the value with the result of `f`, applied to the value at the time
+    /// the operation is published, after all operations appended before it.
+    pub fn update<F>(&mut self, f: F)
+    where
+        F: FnOnce(&T) -> T + Send + 'static,
+    {
+        self.handle.append(Operation::Modify(Box::new(move |value| {
+            *value = f(value);
+            true
+        })));
+    }
+
+    /// Sets `new` only if the value is equal to `expected` at the time the operation
+    /// is published, after all operations appended before it.
+    /// Whether it did is known once the operation is published, see [`CompareAndSet`].
+    pub fn compare_and_set(&mut self, expected: T, new: T) -> CompareAndSet
+    where
+        T: PartialEq + Send + 'static,
+    {
+        let outcome = Arc::new(OnceLock::new());
+        let result = CompareAndSet(outcome.clone());
+        self.handle.append(Operation::Modify(Box::new(move |value| {
+            let matches = *value == expected;
+            if matches {
+                *value = new;
+            }
+            let _ = outcome.set(matches);
+            matches
+        })));
+        result
+    }
+
+    /// Changes the value in place, at the time the operation is published.
+    pub fn modify<F>(&mut self, f: F)
+    where
+        F: FnOnce(&mut T) + Send + 'static,
+    {
+        self.handle.append(Operation::Modify(Box::new(move |value| {
+            f(value);
+            true
+        })));
     }
 
     pub fn publish(&mut self) {
-        self.0.publish();
+        self.handle.publish();
+        self.shared.notify(|state| state.version += 1);
     }
 
     pub fn has_pending(&self) -> bool {
-        self.0.has_pending_operations()
+        self.handle.has_pending_operations()
+    }
+}
+
+/// Outcome of [`WriteHandle::compare_and_set`].
+#[derive(Debug, Clone)]
+#[must_use = "outcome tells whether the value was set"]
+pub struct CompareAndSet(Arc<OnceLock<bool>>);
+
+impl CompareAndSet {
+    /// Returns whether the value was set, or `None` while the operation is not applied yet.
+    /// Operations are applied at the latest by the `publish`, that makes them visible,
+    /// or when the writer is dropped. Before the first publish they are applied right away.
+    pub fn outcome(&self) -> Option<bool> {
+        self.0.get().copied()
+    }
+}
+
+impl<T: Clone> Drop for WriteHandle<T> {
+    fn drop(&mut self) {
+        // pending operations are published on drop, readers then get their result
+        // as the last value, without ever seeing it published
+        let flushed = self.handle.has_pending_operations();
+        self.handle.flush();
+        let last = self.handle.enter().and_then(|inner| inner.0.clone());
+        if let Some(last) = last {
+            let _ = self.shared.last.set(last);
+        }
+        self.shared.notify(|state| {
+            state.version += u64::from(flushed);
+            state.closed = true;
+        });
     }
 }
 
 pub fn new<T: Clone>(value: T) -> (WriteHandle<T>, ReadHandle<T>) {
-    let (w, r) = left_right::new_from_empty::<Inner<T>, SetOperation<T>>(Inner(value));
-    (WriteHandle(w), ReadHandle(r))
+    handles(Some(value))
 }
 
 pub fn new_default<T: Clone + Default>() -> (WriteHandle<T>, ReadHandle<T>) {
-    let (w, r) = left_right::new_from_empty::<Inner<T>, SetOperation<T>>(Inner::default());
-    (WriteHandle(w), ReadHandle(r))
+    handles(Some(T::default()))
+}
+
+/// Creates a cell without a value, readers get [`Read::NotPublished`] until the first publish.
+pub fn new_unpublished<T: Clone>() -> (WriteHandle<T>, ReadHandle<T>) {
+    handles(None)
+}
+
+fn handles<T: Clone>(value: Option<T>) -> (WriteHandle<T>, ReadHandle<T>) {
+    let (handle, r) = left_right::new_from_empty::<Inner<T>, Operation<T>>(Inner(value));
+    let shared = Arc::new(Shared {
+        last: OnceLock::new(),
+        state: Mutex::default(),
+        condvar: Condvar::new(),
+    });
+    let w = WriteHandle {
+        handle,
+        shared: shared.clone(),
+    };
+    let r = ReadHandle {
+        handle: r,
+        shared,
+        seen: 0,
+    };
+    (w, r)
 }
 
 #[cfg(test)]
@@ -98,6 +388,7 @@ mod tests {
     #[test]
     fn it_works() {
         let (mut w, r) = super::new(false);
+        let reader = r.clone();
 
         let t = thread::spawn(move || loop {
             let value = r.get().unwrap();
@@ -110,6 +401,105 @@ mod tests {
         w.set(true);
         w.publish();
         t.join().unwrap();
-        assert!(true);
+        assert!(*reader.get().unwrap());
+    }
+
+    #[test]
+    fn readiness() {
+        use super::Read;
+
+        let (mut w, r) = super::new_unpublished::<u64>();
+        w.update(|v| v + 1);
+        w.set(1);
+        assert!(matches!(r.read(), Read::NotPublished));
+        w.publish();
+        assert!(matches!(r.read(), Read::Value(v) if *v == 1));
+        assert!(!r.is_closed());
+
+        // last value survives the writer, including operations pending at its drop
+        w.set(2);
+        drop(w);
+        assert!(r.is_closed());
+        assert!(matches!(r.read(), Read::Closed(v) if *v == 2));
+        assert_eq!(*r.clone().get().unwrap(), 2);
+
+        let (w, r) = super::new_unpublished::<u64>();
+        drop(w);
+        assert!(r.is_closed());
+        assert!(r.get().is_none());
+    }
+
+    #[test]
+    fn wait_changed() {
+        let (mut w, mut r) = super::new(0u64);
+        assert_eq!(r.wait_changed(Duration::from_millis(1)), Ok(None));
+
+        let t = thread::spawn(move || {
+            let version = r.wait_changed(Duration::from_secs(10)).unwrap();
+            let value = *r.get().unwrap();
+            (version, value, r)
+        });
+        w.set(1);
+        w.publish();
+        let (version, value, mut r) = t.join().unwrap();
+        assert_eq!((version, value), (Some(1), 1));
+
+        // pending operations of a dropped writer count as a change
+        w.set(2);
+        drop(w);
+        assert_eq!(r.wait_changed(Duration::ZERO), Ok(Some(2)));
+        assert_eq!(r.wait_changed(Duration::ZERO), Err(super::Closed));
+    }
+
+    #[cfg(not(loom))]
+    #[tokio::test]
+    async fn changed() {
+        let (mut w, mut r) = super::new(0u64);
+        let task = tokio::spawn(async move {
+            let mut seen = Vec::new();
+            while r.changed().await.is_ok() {
+                seen.push(*r.get().unwrap());
+            }
+            seen
+        });
+
+        for value in 1..=3 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            w.set(value);
+            w.publish();
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        drop(w);
+        assert_eq!(task.await.unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn updates_converge() {
+        let (mut w, r) = super::new(1u64);
+        w.publish();
+        w.update(|v| v * 10);
+        let first = w.compare_and_set(10, 20);
+        let second = w.compare_and_set(10, 30);
+        assert_eq!(first.outcome(), None);
+        w.publish();
+        assert_eq!(*r.get().unwrap(), 20);
+        assert_eq!(
+            (first.outcome(), second.outcome()),
+            (Some(true), Some(false))
+        );
+
+        // the other copy takes results of the first one, so it converges too
+        let mut calls = 0;
+        w.modify(move |v| {
+            calls += 1;
+            *v += calls;
+        });
+        w.publish();
+        assert_eq!(*r.get().unwrap(), 21);
+        let _ = w.compare_and_set(0, 0);
+        w.publish();
+        assert_eq!(*r.get().unwrap(), 21);
+        w.publish();
+        assert_eq!(*r.get().unwrap(), 21);
     }
 }
@@ -1,7 +1,9 @@
 use arc_swap::access::Access;
 use arc_swap::ArcSwap;
+use cell::SharedCell;
 use std::sync::{Arc, Mutex, RwLock};
 
+pub mod cell;
 pub mod left_right_cell;
 
 pub trait ValueManipulator: Clone + Send {
@@ -9,7 +11,16 @@ pub trait ValueManipulator: Clone + Send {
     fn set_value(&self, val: u64);
 }
 
-fn main() {}
+/// Every cell backend can be benchmarked, including [`cell::Cell`] picked by the features.
+impl<C: SharedCell<u64>> ValueManipulator for C {
+    fn get_value(&self) -> u64 {
+        self.load()
+    }
+
+    fn set_value(&self, val: u64) {
+        self.store(val)
+    }
+}
 
 // Arc Swap
 #[derive(Default, Clone)]
@@ -0,0 +1,27 @@
+//! Loom models of `left_right_cell`. Run with
+//! `RUSTFLAGS="--cfg loom" cargo test -p arcswap-vs-leftright --test loom --release`.
+#![cfg(loom)]
+
+use arcswap_vs_leftright::left_right_cell;
+use loom::thread;
+
+#[test]
+fn readers_see_only_published_values() {
+    loom::model(|| {
+        let (mut w, r) = left_right_cell::new(0u8);
+        let reader = r.clone();
+        let t = thread::spawn(move || {
+            for _ in 0..2 {
+                // 1 is overwritten before the publish, so it must never be observed
+                let value = *reader.get().unwrap();
+                assert!(value == 0 || value == 2, "observed unpublished {}", value);
+            }
+        });
+
+        w.set(1);
+        w.set(2);
+        w.publish();
+        t.join().unwrap();
+        assert_eq!(*r.get().unwrap(), 2);
+    });
+}
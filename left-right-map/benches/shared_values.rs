@@ -0,0 +1,92 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use std::sync::Arc;
+
+const VALUE_SIZE: usize = 4 * 1024;
+
+fn value() -> Vec<u8> {
+    vec![42u8; VALUE_SIZE]
+}
+
+// same as `example_usage` test, insert under every key and publish
+fn insert_and_publish(num_keys: u64) {
+    let (mut w, r) = left_right_map::new::<u64, Vec<u8>>();
+    for i in 0..num_keys {
+        w.insert(i, value());
+        w.publish();
+    }
+    black_box(r.len());
+}
+
+fn insert_and_publish_shared(num_keys: u64) {
+    let (mut w, r) = left_right_map::new_shared::<u64, Vec<u8>>();
+    for i in 0..num_keys {
+        w.insert(i, Arc::new(value()));
+        w.publish();
+    }
+    black_box(r.len());
+}
+
+// same as `replace_post_refresh` test, update already published keys
+fn replace(num_keys: u64) {
+    let (mut w, r) = left_right_map::new::<u64, Vec<u8>>();
+    for i in 0..num_keys {
+        w.insert(i, value());
+    }
+    w.publish();
+    for i in 0..num_keys {
+        w.update(i, value());
+    }
+    w.publish();
+    w.publish();
+    black_box(r.len());
+}
+
+fn replace_shared(num_keys: u64) {
+    let (mut w, r) = left_right_map::new_shared::<u64, Vec<u8>>();
+    for i in 0..num_keys {
+        w.insert(i, Arc::new(value()));
+    }
+    w.publish();
+    for i in 0..num_keys {
+        w.update(i, Arc::new(value()));
+    }
+    w.publish();
+    w.publish();
+    black_box(r.len());
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Shared-values");
+
+    let key_counts: Vec<u64> = vec![100, 1_000, 10_000];
+
+    for num_keys in key_counts {
+        group.bench_with_input(
+            BenchmarkId::new("Insert-publish", num_keys),
+            &num_keys,
+            |b, &num_keys| b.iter(|| insert_and_publish(num_keys)),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("Insert-publish-shared", num_keys),
+            &num_keys,
+            |b, &num_keys| b.iter(|| insert_and_publish_shared(num_keys)),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("Replace", num_keys),
+            &num_keys,
+            |b, &num_keys| b.iter(|| replace(num_keys)),
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("Replace-shared", num_keys),
+            &num_keys,
+            |b, &num_keys| b.iter(|| replace_shared(num_keys)),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,242 @@
+use crate::read::ReadHandle;
+use crate::write::{Modifier, Operation, Predicate, WriteHandle};
+use std::collections::hash_map::RandomState;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+
+/// When the background writer publishes collected operations.
+#[derive(Debug, Clone)]
+pub struct AsyncWriterConfig {
+    /// Publish pending operations at least this often.
+    pub publish_interval: Duration,
+    /// Publish as soon as this many operations are pending.
+    pub max_pending: usize,
+}
+
+impl Default for AsyncWriterConfig {
+    fn default() -> Self {
+        Self {
+            publish_interval: Duration::from_millis(100),
+            max_pending: 1000,
+        }
+    }
+}
+
+enum Command<K, V> {
+    Op(Operation<K, V, Infallible>),
+    Publish(oneshot::Sender<()>),
+}
+
+/// Returned when the background writer task is not running anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriterClosed;
+
+impl fmt::Display for WriterClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "background writer task is closed")
+    }
+}
+
+impl Error for WriterClosed {}
+
+/// Writer shareable between tokio tasks without locking.
+///
+/// Operations are sent over a channel to a single background task, which appends them to
+/// the map and publishes them in batches. Publishing waits for readers, so it runs on the
+/// blocking thread pool and never blocks tokio worker threads. Once all handles are dropped,
+/// the background task publishes what is left and returns the `WriteHandle`.
+pub struct AsyncWriteHandle<K, V, S = RandomState>
+where
+    K: Eq + Hash,
+{
+    sender: mpsc::UnboundedSender<Command<K, V>>,
+    read_handle: ReadHandle<K, V, S>,
+}
+
+impl<K, V, S> Clone for AsyncWriteHandle<K, V, S>
+where
+    K: Eq + Hash,
+{
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            read_handle: ReadHandle::clone(&self.read_handle),
+        }
+    }
+}
+
+impl<K, V, S> fmt::Debug for AsyncWriteHandle<K, V, S>
+where
+    K: Eq + Hash,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AsyncWriteHandle")
+            .field("sender", &self.sender)
+            .field("read_handle", &self.read_handle)
+            .finish()
+    }
+}
+
+impl<K, V, S> AsyncWriteHandle<K, V, S>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Spawns the background writer task on the current tokio runtime.
+    pub fn spawn(
+        handle: WriteHandle<K, V, S>,
+        config: AsyncWriterConfig,
+    ) -> (Self, JoinHandle<WriteHandle<K, V, S>>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let read_handle = ReadHandle::clone(handle.read_handle());
+        let task = tokio::spawn(run(handle, receiver, config));
+        (
+            Self {
+                sender,
+                read_handle,
+            },
+            task,
+        )
+    }
+}
+
+impl<K, V, S> AsyncWriteHandle<K, V, S>
+where
+    K: Eq + Hash,
+{
+    fn add_op(&self, op: Operation<K, V, Infallible>) -> Result<(), WriterClosed> {
+        self.sender.send(Command::Op(op)).map_err(|_| WriterClosed)
+    }
+
+    /// Read handle of the map, this writer writes into.
+    pub fn read_handle(&self) -> &ReadHandle<K, V, S> {
+        &self.read_handle
+    }
+
+    /// Publishes all operations sent by this handle so far, and waits until they are visible.
+    pub async fn publish(&self) -> Result<(), WriterClosed> {
+        let (ack, done) = oneshot::channel();
+        self.sender
+            .send(Command::Publish(ack))
+            .map_err(|_| WriterClosed)?;
+        done.await.map_err(|_| WriterClosed)
+    }
+
+    pub fn insert(&self, k: K, v: V) -> Result<(), WriterClosed> {
+        self.add_op(Operation::Add(k, v))
+    }
+
+    pub fn remove(&self, k: K) -> Result<(), WriterClosed> {
+        self.add_op(Operation::Remove(k))
+    }
+
+    pub fn update(&self, k: K, v: V) -> Result<(), WriterClosed> {
+        self.add_op(Operation::Replace(k, v, Instant::now()))
+    }
+
+    pub fn purge(&self) -> Result<(), WriterClosed> {
+        self.add_op(Operation::Purge)
+    }
+
+    pub fn modify<F>(&self, k: K, modifier: F) -> Result<(), WriterClosed>
+    where
+        F: Fn(&mut V) + Send + 'static,
+    {
+        self.add_op(Operation::Modify(
+            k,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ))
+    }
+
+    pub fn insert_if_absent(&self, k: K, v: V) -> Result<(), WriterClosed> {
+        self.add_op(Operation::AddIfAbsent(k, v))
+    }
+
+    pub fn upsert<F>(&self, k: K, default: V, modifier: F) -> Result<(), WriterClosed>
+    where
+        F: Fn(&mut V) + Send + 'static,
+    {
+        self.add_op(Operation::Upsert(
+            k,
+            default,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ))
+    }
+
+    pub fn remove_if<F>(&self, k: K, predicate: F) -> Result<(), WriterClosed>
+    where
+        F: Fn(&V) -> bool + Send + 'static,
+    {
+        self.add_op(Operation::RemoveIf(k, Predicate(Box::new(predicate))))
+    }
+}
+
+async fn run<K, V, S>(
+    mut handle: WriteHandle<K, V, S>,
+    mut receiver: mpsc::UnboundedReceiver<Command<K, V>>,
+    config: AsyncWriterConfig,
+) -> WriteHandle<K, V, S>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    let mut interval = tokio::time::interval(config.publish_interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut pending = 0;
+    let mut acks = Vec::new();
+
+    loop {
+        let publish = tokio::select! {
+            command = receiver.recv() => match command {
+                Some(Command::Op(op)) => {
+                    handle.add_op(op.cast());
+                    pending += 1;
+                    pending >= config.max_pending
+                }
+                Some(Command::Publish(ack)) => {
+                    acks.push(ack);
+                    true
+                }
+                None => break,
+            },
+            _ = interval.tick() => pending > 0,
+        };
+
+        if publish {
+            handle = publish_blocking(handle).await;
+            pending = 0;
+            acks.drain(..).for_each(|ack| {
+                let _ = ack.send(());
+            });
+        }
+    }
+
+    if pending > 0 {
+        handle = publish_blocking(handle).await;
+    }
+    handle
+}
+
+async fn publish_blocking<K, V, S>(mut handle: WriteHandle<K, V, S>) -> WriteHandle<K, V, S>
+where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        handle.publish();
+        handle
+    })
+    .await
+    .expect("publish does not panic")
+}
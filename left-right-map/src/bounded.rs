@@ -0,0 +1,194 @@
+//! Capacity-bounded map, created by [`crate::new_bounded`].
+//!
+//! Readers can not record their accesses, since they only ever see an immutable copy, so usage
+//! of an entry is counted by the writer: inserting, updating or modifying a key uses it,
+//! and [`crate::write::WriteHandle::touch`] can report reader hits. Eviction runs while
+//! operations are applied and depends only on the order of operations, so both copies of the
+//! map evict the same keys.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+type Weigher<K, V> = Arc<dyn Fn(&K, &V) -> usize + Send + Sync>;
+
+/// Which entry is evicted first, once the map is over its capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Least recently used entry.
+    Lru,
+    /// Least frequently used entry, ties are broken by the least recently used one.
+    Lfu,
+}
+
+/// Limits of a bounded map.
+pub struct Capacity<K, V> {
+    max_entries: usize,
+    max_weight: Option<(usize, Weigher<K, V>)>,
+    policy: EvictionPolicy,
+}
+
+impl<K, V> Clone for Capacity<K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            max_entries: self.max_entries,
+            max_weight: self.max_weight.clone(),
+            policy: self.policy,
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Capacity<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Capacity")
+            .field("max_entries", &self.max_entries)
+            .field("max_weight", &self.max_weight.as_ref().map(|(max, _)| max))
+            .field("policy", &self.policy)
+            .finish()
+    }
+}
+
+impl<K, V> Capacity<K, V> {
+    pub fn new(max_entries: usize, policy: EvictionPolicy) -> Self {
+        Self {
+            max_entries,
+            max_weight: None,
+            policy,
+        }
+    }
+
+    /// Additionally limits the total weight of entries, e.g. their size in bytes.
+    /// Weigher must be deterministic, because it is run once for each copy of the map.
+    pub fn with_weigher<F>(mut self, max_weight: usize, weigher: F) -> Self
+    where
+        F: Fn(&K, &V) -> usize + Send + Sync + 'static,
+    {
+        self.max_weight = Some((max_weight, Arc::new(weigher)));
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Usage {
+    uses: u64,
+    tick: u64,
+    weight: usize,
+}
+
+/// Usage bookkeeping of one copy of a bounded map.
+#[derive(Clone)]
+pub(crate) struct Eviction<K, V> {
+    capacity: Capacity<K, V>,
+    usage: HashMap<K, Usage>,
+    /// Keys ordered by eviction priority, the first one is evicted first.
+    order: BTreeMap<(u64, u64), K>,
+    tick: u64,
+    weight: usize,
+    /// Key handed out mutably, it has to be reweighed once the operation is done.
+    touched: Option<K>,
+}
+
+impl<K, V> fmt::Debug for Eviction<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Eviction")
+            .field("capacity", &self.capacity)
+            .field("len", &self.usage.len())
+            .field("weight", &self.weight)
+            .finish()
+    }
+}
+
+impl<K, V> Eviction<K, V>
+where
+    K: Eq + Hash + Clone,
+{
+    pub(crate) fn new(capacity: Capacity<K, V>) -> Self {
+        Self {
+            capacity,
+            usage: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            weight: 0,
+            touched: None,
+        }
+    }
+
+    fn rank(&self, usage: &Usage) -> (u64, u64) {
+        match self.capacity.policy {
+            EvictionPolicy::Lru => (0, usage.tick),
+            EvictionPolicy::Lfu => (usage.uses, usage.tick),
+        }
+    }
+
+    fn weigh(&self, key: &K, value: &V) -> usize {
+        self.capacity
+            .max_weight
+            .as_ref()
+            .map_or(0, |(_, weigher)| weigher(key, value))
+    }
+
+    /// Records a use of `key`, that now holds `value`.
+    pub(crate) fn used(&mut self, key: &K, value: &V) {
+        let weight = self.weigh(key, value);
+        let uses = match self.forget(key) {
+            Some(usage) => usage.uses + 1,
+            None => 1,
+        };
+        self.tick += 1;
+        let usage = Usage {
+            uses,
+            tick: self.tick,
+            weight,
+        };
+        self.order.insert(self.rank(&usage), key.clone());
+        self.usage.insert(key.clone(), usage);
+        self.weight += weight;
+    }
+
+    /// Remembers key, that is being modified in place, so it can be reweighed afterwards.
+    pub(crate) fn touch(&mut self, key: &K) {
+        self.touched = Some(key.clone());
+    }
+
+    pub(crate) fn take_touched(&mut self) -> Option<K> {
+        self.touched.take()
+    }
+
+    pub(crate) fn remove(&mut self, key: &K) {
+        self.forget(key);
+    }
+
+    fn forget(&mut self, key: &K) -> Option<Usage> {
+        let usage = self.usage.remove(key)?;
+        self.order.remove(&self.rank(&usage));
+        self.weight -= usage.weight;
+        Some(usage)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.usage.clear();
+        self.order.clear();
+        self.weight = 0;
+    }
+
+    fn is_over(&self) -> bool {
+        self.usage.len() > self.capacity.max_entries
+            || self
+                .capacity
+                .max_weight
+                .as_ref()
+                .is_some_and(|(max, _)| self.weight > *max)
+    }
+
+    /// Returns the next key to evict, if the map is over its capacity.
+    pub(crate) fn victim(&mut self) -> Option<K> {
+        if !self.is_over() {
+            return None;
+        }
+        let (_, key) = self.order.pop_first()?;
+        let usage = self.usage.remove(&key).expect("ordered keys are tracked");
+        self.weight -= usage.weight;
+        Some(key)
+    }
+}
@@ -0,0 +1,134 @@
+#[cfg(feature = "metrics")]
+use crate::metrics::{PublishMetrics, Recorder};
+use crate::sync::{Condvar, Mutex};
+use std::fmt;
+#[cfg(feature = "metrics")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+
+/// Shared between writer and readers, counts publishes and wakes up readers waiting for them.
+#[derive(Debug, Default)]
+pub(crate) struct Notifier {
+    state: Mutex<State>,
+    condvar: Condvar,
+    #[cfg(feature = "metrics")]
+    readers: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    epoch: u64,
+    closed: bool,
+    wakers: Vec<Waker>,
+}
+
+impl Notifier {
+    pub(crate) fn epoch(&self) -> u64 {
+        self.state.lock().unwrap().epoch
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn reader_added(&self) {
+        self.readers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "metrics")]
+    pub(crate) fn reader_dropped(&self) {
+        self.readers.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    fn notify(&self, f: impl FnOnce(&mut State)) {
+        let wakers = {
+            let mut state = self.state.lock().unwrap();
+            f(&mut state);
+            std::mem::take(&mut state.wakers)
+        };
+        self.condvar.notify_all();
+        wakers.into_iter().for_each(Waker::wake);
+    }
+
+    /// Blocks until epoch is greater than `since`. Returns None if writer got dropped before.
+    pub(crate) fn wait(&self, since: u64) -> Option<u64> {
+        let mut state = self.state.lock().unwrap();
+        while state.epoch <= since && !state.closed {
+            state = self.condvar.wait(state).unwrap();
+        }
+        (state.epoch > since).then_some(state.epoch)
+    }
+
+    pub(crate) fn poll_wait(&self, since: u64, cx: &mut Context<'_>) -> Poll<Option<u64>> {
+        let mut state = self.state.lock().unwrap();
+        if state.epoch > since {
+            return Poll::Ready(Some(state.epoch));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        if !state.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+            state.wakers.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+/// Writer side of the [`Notifier`]. Closes the notifier once dropped, so waiting readers
+/// do not wait forever for a writer, that is gone.
+pub(crate) struct Publisher {
+    notifier: Arc<Notifier>,
+    #[cfg(feature = "metrics")]
+    pub(crate) recorder: Option<Arc<dyn Recorder>>,
+}
+
+impl fmt::Debug for Publisher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Publisher").field(&self.notifier).finish()
+    }
+}
+
+impl Publisher {
+    pub(crate) fn new(notifier: Arc<Notifier>) -> Self {
+        Self {
+            notifier,
+            #[cfg(feature = "metrics")]
+            recorder: None,
+        }
+    }
+
+    /// Has to be called after the publish, so readers observing new epoch also see published data.
+    pub(crate) fn published(&self) {
+        self.notifier.notify(|state| state.epoch += 1);
+    }
+
+    /// Whether the writer published at least once. Before that, left-right applies operations
+    /// to the writer's copy right away, so they are not pending, yet readers do not see them.
+    pub(crate) fn has_published(&self) -> bool {
+        self.notifier.epoch() > 0
+    }
+
+    /// Reports publish, that started at `started`, to the recorder if there is one.
+    #[cfg(feature = "metrics")]
+    pub(crate) fn record(
+        &self,
+        started: std::time::Instant,
+        operations: usize,
+        len: impl FnOnce() -> usize,
+    ) {
+        let Some(recorder) = &self.recorder else {
+            return;
+        };
+        recorder.record_publish(&PublishMetrics {
+            wait: started.elapsed(),
+            operations,
+            len: len(),
+            readers: self.notifier.readers.load(Ordering::Relaxed),
+            epoch: self.notifier.epoch(),
+        });
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        self.notifier.notify(|state| state.closed = true);
+    }
+}
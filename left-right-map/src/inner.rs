@@ -1,35 +1,331 @@
-use std::collections::HashMap;
-use std::hash::Hash;
+use crate::bounded::{Capacity, Eviction};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::time::Instant;
 
 #[derive(Debug, Clone)]
-pub(crate) struct Inner<K, V>
+pub(crate) struct Inner<K, V, S = RandomState>
 where
     K: Eq + Hash,
 {
-    pub(crate) data: HashMap<K, V>,
+    pub(crate) data: HashMap<K, V, S>,
+    /// Deadlines of entries inserted with a TTL. Expired entries stay in `data` until swept,
+    /// readers just skip them.
+    pub(crate) expiry: HashMap<K, Instant, S>,
+    /// Set only for bounded maps.
+    pub(crate) eviction: Option<Eviction<K, V>>,
+    /// Keys of updates, that found no entry since the start of the current batch.
+    /// Only recorded while the writer is in strict update mode.
+    pub(crate) missed: Option<Vec<K>>,
     pub(crate) ready: bool,
 }
 
-impl<K, V> Default for Inner<K, V>
+impl<K, V, S> Default for Inner<K, V, S>
 where
     K: Eq + Hash,
+    S: BuildHasher + Default,
 {
     fn default() -> Self {
         Inner {
-            data: HashMap::new(),
+            data: HashMap::default(),
+            expiry: HashMap::default(),
+            eviction: None,
+            missed: None,
             ready: false,
         }
     }
 }
 
-impl<K, V> Inner<K, V>
+impl<K, V, S> Inner<K, V, S>
 where
     K: Eq + Hash,
+    S: BuildHasher + Clone,
 {
-    pub(crate) fn with_capacity(capacity: usize) -> Self {
+    pub(crate) fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
         Inner {
-            data: HashMap::with_capacity(capacity),
+            data: HashMap::with_capacity_and_hasher(capacity, hasher.clone()),
+            expiry: HashMap::with_hasher(hasher),
+            eviction: None,
+            missed: None,
             ready: false,
         }
     }
+
+    pub(crate) fn bounded(capacity: Capacity<K, V>, hasher: S) -> Self
+    where
+        K: Clone,
+    {
+        Inner {
+            eviction: Some(Eviction::new(capacity)),
+            ..Self::with_capacity_and_hasher(0, hasher)
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn from_data(data: HashMap<K, V, S>, expiry: Vec<(K, Instant)>) -> Self {
+        let mut deadlines = HashMap::with_hasher(data.hasher().clone());
+        deadlines.extend(expiry);
+        Inner {
+            expiry: deadlines,
+            eviction: None,
+            missed: None,
+            data,
+            ready: true,
+        }
+    }
+}
+
+impl<K, V, S> Inner<K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    /// Returns true if `key` has a deadline, that passed before `now`.
+    pub(crate) fn is_expired<Q>(&self, key: &Q, now: Instant) -> bool
+    where
+        K: std::borrow::Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.expiry.get(key).is_some_and(|at| *at <= now)
+    }
+
+    /// Reweighs entry modified by the last operation and evicts entries over the capacity.
+    /// Called after each applied operation, on both copies.
+    pub(crate) fn settle(&mut self)
+    where
+        K: Clone,
+    {
+        let Some(eviction) = &mut self.eviction else {
+            return;
+        };
+        if let Some(key) = eviction.take_touched() {
+            if let Some(value) = self.data.get(&key) {
+                eviction.used(&key, value);
+            }
+        }
+        while let Some(key) = eviction.victim() {
+            self.data.remove(&key);
+            self.expiry.remove(&key);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct OrderedInner<K, V> {
+    pub(crate) data: BTreeMap<K, V>,
+    pub(crate) ready: bool,
+}
+
+impl<K, V> Default for OrderedInner<K, V> {
+    fn default() -> Self {
+        OrderedInner {
+            data: BTreeMap::new(),
+            ready: false,
+        }
+    }
+}
+
+/// Access to the content of a map copy, so writer operations are applied the same way
+/// regardless of the collection backing the map.
+pub(crate) trait Store<K, V> {
+    fn get(&self, key: &K) -> Option<&V>;
+    fn get_mut(&mut self, key: &K) -> Option<&mut V>;
+    fn insert(&mut self, key: K, value: V);
+    fn remove(&mut self, key: &K) -> Option<V>;
+    fn clear(&mut self);
+    fn retain(&mut self, predicate: &dyn Fn(&K, &V) -> bool);
+    fn reserve(&mut self, additional: usize);
+    fn mark_ready(&mut self);
+    fn expiry(&self, key: &K) -> Option<Instant>;
+    fn set_expiry(&mut self, key: K, at: Instant);
+    /// Removes all entries with deadline before `now`.
+    fn remove_expired(&mut self, now: Instant);
+    /// Starts recording missed updates from scratch, or stops recording them.
+    fn report_missed(&mut self, enabled: bool);
+    /// Records an update of `key`, that did nothing because the key is missing.
+    fn missed(&mut self, key: &K);
+    /// Like `get_mut`, but an entry expired at `now` counts as missing, as it does for readers.
+    fn get_live_mut(&mut self, key: &K, now: Instant) -> Option<&mut V> {
+        if self.expiry(key).is_some_and(|at| at <= now) {
+            return None;
+        }
+        self.get_mut(key)
+    }
+    /// Whole content of the copy, that can be swapped in without reinserting its entries.
+    type Data;
+    /// Replaces all entries by `data`.
+    fn replace_all(&mut self, data: Self::Data);
+}
+
+impl<K, V, S> Store<K, V> for Inner<K, V, S>
+where
+    K: Eq + Hash + Clone,
+    S: BuildHasher,
+{
+    fn get(&self, key: &K) -> Option<&V> {
+        self.data.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let value = self.data.get_mut(key)?;
+        // only an existing entry is used, a missing one is counted once inserted
+        if let Some(eviction) = &mut self.eviction {
+            eviction.touch(key);
+        }
+        Some(value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        if !self.expiry.is_empty() {
+            self.expiry.remove(&key);
+        }
+        if let Some(eviction) = &mut self.eviction {
+            eviction.used(&key, &value);
+        }
+        self.data.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        if !self.expiry.is_empty() {
+            self.expiry.remove(key);
+        }
+        if let Some(eviction) = &mut self.eviction {
+            eviction.remove(key);
+        }
+        self.data.remove(key)
+    }
+
+    fn clear(&mut self) {
+        self.data.clear();
+        self.expiry.clear();
+        if let Some(eviction) = &mut self.eviction {
+            eviction.clear();
+        }
+    }
+
+    fn retain(&mut self, predicate: &dyn Fn(&K, &V) -> bool) {
+        let expiry = &mut self.expiry;
+        let eviction = &mut self.eviction;
+        self.data.retain(|key, value| {
+            let keep = predicate(key, value);
+            if !keep {
+                expiry.remove(key);
+                if let Some(eviction) = eviction {
+                    eviction.remove(key);
+                }
+            }
+            keep
+        });
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+    }
+
+    fn mark_ready(&mut self) {
+        self.ready = true;
+    }
+
+    fn expiry(&self, key: &K) -> Option<Instant> {
+        self.expiry.get(key).copied()
+    }
+
+    fn set_expiry(&mut self, key: K, at: Instant) {
+        self.expiry.insert(key, at);
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        let data = &mut self.data;
+        let eviction = &mut self.eviction;
+        self.expiry.retain(|key, at| {
+            if *at <= now {
+                data.remove(key);
+                if let Some(eviction) = eviction {
+                    eviction.remove(key);
+                }
+                return false;
+            }
+            true
+        });
+    }
+
+    fn report_missed(&mut self, enabled: bool) {
+        self.missed = enabled.then(Vec::new);
+    }
+
+    fn missed(&mut self, key: &K) {
+        if let Some(missed) = &mut self.missed {
+            missed.push(key.clone());
+        }
+    }
+
+    type Data = HashMap<K, V, S>;
+
+    fn replace_all(&mut self, data: HashMap<K, V, S>) {
+        self.expiry.clear();
+        if let Some(eviction) = &mut self.eviction {
+            // clone of a map iterates in the same order, so both copies evict the same keys
+            eviction.clear();
+            for (key, value) in &data {
+                eviction.used(key, value);
+            }
+        }
+        self.data = data;
+    }
+}
+
+impl<K, V> Store<K, V> for OrderedInner<K, V>
+where
+    K: Ord,
+{
+    fn get(&self, key: &K) -> Option<&V> {
+        self.data.get(key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.data.get_mut(key)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        self.data.insert(key, value);
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        self.data.remove(key)
+    }
+
+    fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    fn retain(&mut self, predicate: &dyn Fn(&K, &V) -> bool) {
+        self.data.retain(|key, value| predicate(key, value));
+    }
+
+    fn reserve(&mut self, _additional: usize) {}
+
+    fn mark_ready(&mut self) {
+        self.ready = true;
+    }
+
+    // ordered map does not expose TTL operations, so its entries never expire
+    fn expiry(&self, _key: &K) -> Option<Instant> {
+        None
+    }
+
+    fn set_expiry(&mut self, _key: K, _at: Instant) {}
+
+    fn remove_expired(&mut self, _now: Instant) {}
+
+    // ordered map has no strict update mode
+    fn report_missed(&mut self, _enabled: bool) {}
+
+    fn missed(&mut self, _key: &K) {}
+
+    type Data = BTreeMap<K, V>;
+
+    fn replace_all(&mut self, data: BTreeMap<K, V>) {
+        self.data = data;
+    }
 }
@@ -1,20 +1,153 @@
-use crate::inner::Inner;
+use crate::epoch::Notifier;
+use crate::inner::{Inner, OrderedInner};
 use crate::read::ReadHandle;
 use crate::write::WriteHandle;
+use std::collections::hash_map::RandomState;
 use std::fmt;
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
 
+#[cfg(feature = "tokio")]
+pub mod async_write;
+pub mod bounded;
+mod epoch;
 mod inner;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod multi;
+pub mod ordered;
 pub mod read;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+mod sync;
+pub mod transaction;
+pub mod view;
+#[cfg(feature = "serde")]
+pub mod wal;
 pub mod write;
 
-pub fn new<K, V>() -> (WriteHandle<K, V>, ReadHandle<K, V>)
+type Handles<K, V, S = RandomState> = (WriteHandle<K, V, S>, ReadHandle<K, V, S>);
+
+pub fn new<K, V>() -> Handles<K, V>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: Clone,
+{
+    with_hasher(RandomState::default())
+}
+
+/// Creates a map, that uses `hasher` to hash its keys.
+pub fn with_hasher<K, V, S>(hasher: S) -> Handles<K, V, S>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    with_capacity_and_hasher(0, hasher)
+}
+
+/// Creates a map, that uses `hasher` to hash its keys, with both copies able to hold
+/// at least `capacity` elements without reallocating.
+pub fn with_capacity_and_hasher<K, V, S>(capacity: usize, hasher: S) -> Handles<K, V, S>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    let inner = Inner::with_capacity_and_hasher(capacity, hasher);
+    let (mut w, r) = left_right::new_from_empty(inner);
+    w.append(write::Operation::MarkReady);
+    let notifier = Arc::new(Notifier::default());
+    (
+        WriteHandle::new(w, notifier.clone()),
+        ReadHandle::new(r, notifier),
+    )
+}
+
+/// Creates a map from a snapshot written by `WriteHandle::snapshot_to`.
+/// Map is ready right away, readers see the restored content without waiting for a publish.
+#[cfg(feature = "serde")]
+pub fn from_snapshot<K, V, R>(reader: R) -> Result<Handles<K, V>, snapshot::SnapshotError>
+where
+    K: Hash + Eq + Clone + fmt::Debug + serde::de::DeserializeOwned,
+    V: Clone + serde::de::DeserializeOwned,
+    R: std::io::Read,
+{
+    let (_, content) = snapshot::read(reader)?;
+    Ok(from_content(content))
+}
+
+/// Creates a map, that is ready right away with the given content.
+#[cfg(feature = "serde")]
+fn from_content<K, V>(content: snapshot::Content<K, V>) -> Handles<K, V>
 where
     K: Hash + Eq + Clone + fmt::Debug,
     V: Clone,
+{
+    let inner = Inner::from_data(content.data, content.expiry);
+    let (w, r) = left_right::new_from_empty(inner);
+    let notifier = Arc::new(Notifier::default());
+    (
+        WriteHandle::new(w, notifier.clone()),
+        ReadHandle::new(r, notifier),
+    )
+}
+
+/// Creates a map, that keeps its values behind `Arc`, so both copies share the same allocation.
+/// Applying an operation to the second copy then costs only a pointer clone instead of a deep
+/// clone of the value, which pays off for large values.
+pub fn new_shared<K, V>() -> (WriteHandle<K, Arc<V>>, ReadHandle<K, Arc<V>>)
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+{
+    new()
+}
+
+/// Creates a map, that holds at most as many entries as `capacity` allows.
+/// Once the writer goes over it, entries are evicted by the policy of `capacity`.
+pub fn new_bounded<K, V>(capacity: bounded::Capacity<K, V>) -> Handles<K, V>
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: Clone,
+{
+    let inner = Inner::bounded(capacity, RandomState::default());
+    let (mut w, r) = left_right::new_from_empty(inner);
+    w.append(write::Operation::MarkReady);
+    let notifier = Arc::new(Notifier::default());
+    (
+        WriteHandle::new(w, notifier.clone()),
+        ReadHandle::new(r, notifier),
+    )
+}
+
+/// Creates a multimap flavour of the map, where every key holds a bag of values.
+pub fn new_multi<K, V>() -> (multi::WriteHandle<K, V>, ReadHandle<K, multi::Values<V>>)
+where
+    K: Hash + Eq + Clone + fmt::Debug,
+    V: Eq + Clone,
 {
     let inner = Inner::default();
     let (mut w, r) = left_right::new_from_empty(inner);
+    w.append(multi::Operation::MarkReady);
+    let notifier = Arc::new(Notifier::default());
+    (
+        multi::WriteHandle::new(w, notifier.clone()),
+        ReadHandle::new(r, notifier),
+    )
+}
+
+/// Creates a map ordered by its keys, which supports range queries on the read side.
+pub fn new_ordered<K, V>() -> (ordered::WriteHandle<K, V>, ordered::ReadHandle<K, V>)
+where
+    K: Ord + Clone + fmt::Debug,
+    V: Clone,
+{
+    let inner = OrderedInner::default();
+    let (mut w, r) = left_right::new_from_empty(inner);
     w.append(write::Operation::MarkReady);
-    (WriteHandle::new(w), ReadHandle::new(r))
+    let notifier = Arc::new(Notifier::default());
+    (
+        ordered::WriteHandle::new(w, notifier.clone()),
+        ordered::ReadHandle::new(r, notifier),
+    )
 }
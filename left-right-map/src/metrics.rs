@@ -0,0 +1,46 @@
+//! Instrumentation of publishes, enabled by the `metrics` feature.
+//!
+//! Install a [`Recorder`] with [`crate::write::WriteHandle::set_recorder`] before sharing the
+//! writer; every publish of the writer and of its shared clones is then reported to it.
+
+use std::time::Duration;
+
+/// Measurements of a single publish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PublishMetrics {
+    /// Time spent publishing, mostly waiting for readers to leave the old copy of the map.
+    pub wait: Duration,
+    /// Operations appended since the previous publish.
+    pub operations: usize,
+    /// Number of entries of the map after the publish, expired entries included.
+    pub len: usize,
+    /// Live read handles of the map, including the one owned by the writer.
+    pub readers: usize,
+    /// Epoch the publish produced.
+    pub epoch: u64,
+}
+
+/// Receives metrics of every publish. Called by the writer right after publishing,
+/// so it has to be cheap.
+pub trait Recorder: Send + Sync {
+    fn record_publish(&self, metrics: &PublishMetrics);
+}
+
+/// Emits every publish as a `tracing` debug event with target `left_right_map::publish`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TracingRecorder;
+
+impl Recorder for TracingRecorder {
+    fn record_publish(&self, metrics: &PublishMetrics) {
+        tracing::debug!(
+            target: "left_right_map::publish",
+            wait_us = metrics.wait.as_micros() as u64,
+            operations = metrics.operations,
+            len = metrics.len,
+            readers = metrics.readers,
+            epoch = metrics.epoch,
+            "published",
+        );
+    }
+}
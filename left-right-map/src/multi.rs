@@ -0,0 +1,224 @@
+use crate::epoch::{Notifier, Publisher};
+use crate::inner::Inner;
+use left_right::Absorb;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::Arc;
+
+/// Bag of values stored under a single key of the multimap.
+/// Duplicates are allowed, order of values is not guaranteed.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Values<V>(Vec<V>);
+
+impl<V> Values<V> {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, V> {
+        self.0.iter()
+    }
+
+    pub fn as_slice(&self) -> &[V] {
+        &self.0
+    }
+}
+
+impl<V: PartialEq> Values<V> {
+    pub fn contains(&self, value: &V) -> bool {
+        self.0.contains(value)
+    }
+}
+
+impl<V: fmt::Debug> fmt::Debug for Values<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.iter()).finish()
+    }
+}
+
+impl<'a, V> IntoIterator for &'a Values<V> {
+    type Item = &'a V;
+    type IntoIter = std::slice::Iter<'a, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+pub struct WriteHandle<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Eq + Clone,
+{
+    handle: left_right::WriteHandle<Inner<K, Values<V>>, Operation<K, V>>,
+    publisher: Publisher,
+}
+
+impl<K, V> fmt::Debug for WriteHandle<K, V>
+where
+    K: Eq + Hash + Clone + fmt::Debug,
+    V: Eq + Clone + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHandle")
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl<K, V> WriteHandle<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Eq + Clone,
+{
+    pub(crate) fn new(
+        handle: left_right::WriteHandle<Inner<K, Values<V>>, Operation<K, V>>,
+        notifier: Arc<Notifier>,
+    ) -> Self {
+        Self {
+            handle,
+            publisher: Publisher::new(notifier),
+        }
+    }
+
+    pub fn publish(&mut self) -> &mut Self {
+        self.handle.publish();
+        self.publisher.published();
+        self
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.handle.has_pending_operations()
+    }
+
+    pub(crate) fn add_op(&mut self, op: Operation<K, V>) -> &mut Self {
+        self.handle.append(op);
+        self
+    }
+
+    /// Adds value to the bag of the key, creating the bag if the key is missing.
+    pub fn insert(&mut self, k: K, v: V) -> &mut Self {
+        self.add_op(Operation::Add(k, v))
+    }
+
+    /// Removes a single occurrence of the value from the bag of the key.
+    /// Key is removed from the map once its bag becomes empty.
+    pub fn remove_value(&mut self, k: K, v: V) -> &mut Self {
+        self.add_op(Operation::RemoveValue(k, v))
+    }
+
+    /// Removes the key together with all of its values.
+    pub fn clear_key(&mut self, k: K) -> &mut Self {
+        self.add_op(Operation::ClearKey(k))
+    }
+
+    pub fn purge(&mut self) -> &mut Self {
+        self.add_op(Operation::Purge)
+    }
+}
+
+pub(crate) enum Operation<K, V> {
+    Add(K, V),
+    RemoveValue(K, V),
+    ClearKey(K),
+    Purge,
+    MarkReady,
+}
+
+impl<K, V> fmt::Debug for Operation<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Operation::Add(ref a, ref b) => f.debug_tuple("Add").field(a).field(b).finish(),
+            Operation::RemoveValue(ref a, ref b) => {
+                f.debug_tuple("RemoveValue").field(a).field(b).finish()
+            }
+            Operation::ClearKey(ref a) => f.debug_tuple("ClearKey").field(a).finish(),
+            Operation::Purge => f.debug_tuple("Purge").finish(),
+            Operation::MarkReady => f.debug_tuple("MarkReady").finish(),
+        }
+    }
+}
+
+impl<K, V> Inner<K, Values<V>>
+where
+    K: Eq + Hash,
+    V: Eq,
+{
+    fn remove_value(&mut self, key: &K, value: &V) {
+        if let Some(values) = self.data.get_mut(key) {
+            // both copies hold the same bag in the same order, so swap_remove stays deterministic
+            if let Some(i) = values.0.iter().position(|v| v == value) {
+                values.0.swap_remove(i);
+            }
+            if values.is_empty() {
+                self.data.remove(key);
+            }
+        }
+    }
+}
+
+impl<K, V> Absorb<Operation<K, V>> for Inner<K, Values<V>>
+where
+    K: Eq + Hash + Clone,
+    V: Eq + Clone,
+{
+    fn absorb_first(&mut self, operation: &mut Operation<K, V>, _other: &Self) {
+        match operation {
+            Operation::Add(ref key, ref value) => {
+                self.data
+                    .entry(key.clone())
+                    .or_insert_with(|| Values(Vec::new()))
+                    .0
+                    .push(value.clone());
+            }
+            Operation::RemoveValue(ref key, ref value) => {
+                self.remove_value(key, value);
+            }
+            Operation::ClearKey(ref key) => {
+                self.data.remove(key);
+            }
+            Operation::Purge => {
+                self.data.clear();
+            }
+            Operation::MarkReady => {
+                self.ready = true;
+            }
+        }
+    }
+
+    fn absorb_second(&mut self, operation: Operation<K, V>, _other: &Self) {
+        match operation {
+            Operation::Add(key, value) => {
+                self.data
+                    .entry(key)
+                    .or_insert_with(|| Values(Vec::new()))
+                    .0
+                    .push(value);
+            }
+            Operation::RemoveValue(key, value) => {
+                self.remove_value(&key, &value);
+            }
+            Operation::ClearKey(key) => {
+                self.data.remove(&key);
+            }
+            Operation::Purge => {
+                self.data.clear();
+            }
+            Operation::MarkReady => {
+                self.ready = true;
+            }
+        }
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.data = first.data.clone();
+        self.ready = first.ready;
+    }
+}
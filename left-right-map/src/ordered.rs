@@ -0,0 +1,337 @@
+use crate::epoch::{Notifier, Publisher};
+use crate::inner::OrderedInner;
+use crate::transaction::{Transaction, TransactionError};
+use crate::write::{Filter, Modifier, Operation, Predicate};
+use left_right::{Absorb, ReadGuard};
+use std::borrow::Borrow;
+use std::collections::{btree_map, BTreeMap};
+use std::fmt;
+use std::future::Future;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+use std::time::Instant;
+
+type OrderedOperation<K, V> = Operation<K, V, BTreeMap<K, V>>;
+
+impl<K, V> Absorb<OrderedOperation<K, V>> for OrderedInner<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn absorb_first(&mut self, operation: &mut OrderedOperation<K, V>, _other: &Self) {
+        operation.apply_first(self);
+    }
+
+    fn absorb_second(&mut self, operation: OrderedOperation<K, V>, _other: &Self) {
+        operation.apply_second(self);
+    }
+
+    fn sync_with(&mut self, first: &Self) {
+        self.data = first.data.clone();
+        self.ready = first.ready;
+    }
+}
+
+/// Writer of the ordered map, supports the same operations as `write::WriteHandle`.
+pub struct WriteHandle<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    handle: left_right::WriteHandle<OrderedInner<K, V>, OrderedOperation<K, V>>,
+    read_handle: ReadHandle<K, V>,
+    publisher: Publisher,
+}
+
+impl<K, V> fmt::Debug for WriteHandle<K, V>
+where
+    K: Ord + Clone + fmt::Debug,
+    V: fmt::Debug + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHandle")
+            .field("handle", &self.handle)
+            .field("read_handle", &self.read_handle)
+            .finish()
+    }
+}
+
+impl<K, V> WriteHandle<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    pub(crate) fn new(
+        handle: left_right::WriteHandle<OrderedInner<K, V>, OrderedOperation<K, V>>,
+        notifier: Arc<Notifier>,
+    ) -> Self {
+        let read_handle =
+            ReadHandle::new(left_right::ReadHandle::clone(&*handle), notifier.clone());
+        Self {
+            handle,
+            read_handle,
+            publisher: Publisher::new(notifier),
+        }
+    }
+
+    pub fn publish(&mut self) -> &mut Self {
+        self.handle.publish();
+        self.publisher.published();
+        self
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.handle.has_pending_operations()
+    }
+
+    pub(crate) fn add_op(&mut self, op: OrderedOperation<K, V>) -> &mut Self {
+        self.handle.append(op);
+        self
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> &mut Self {
+        self.add_op(Operation::Add(k, v))
+    }
+
+    pub fn remove(&mut self, k: K) -> &mut Self {
+        self.add_op(Operation::Remove(k))
+    }
+
+    pub fn update(&mut self, k: K, v: V) -> &mut Self {
+        self.add_op(Operation::Replace(k, v, Instant::now()))
+    }
+
+    pub fn purge(&mut self) -> &mut Self {
+        self.add_op(Operation::Purge)
+    }
+
+    pub fn retain<F>(&mut self, predicate: F) -> &mut Self
+    where
+        F: Fn(&K, &V) -> bool + Send + 'static,
+    {
+        self.add_op(Operation::Retain(Filter(Box::new(predicate))))
+    }
+
+    pub fn extend<I>(&mut self, entries: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.add_op(Operation::Extend(entries.into_iter().collect()))
+    }
+
+    /// Replaces the whole content of the map with `entries`. The map is cloned once
+    /// for the first copy and moved into the second one.
+    pub fn replace_all(&mut self, entries: BTreeMap<K, V>) -> &mut Self {
+        self.add_op(Operation::ReplaceAll(entries))
+    }
+
+    pub fn modify<F>(&mut self, k: K, modifier: F) -> &mut Self
+    where
+        F: Fn(&mut V) + Send + 'static,
+    {
+        self.add_op(Operation::Modify(
+            k,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ))
+    }
+
+    pub fn insert_if_absent(&mut self, k: K, v: V) -> &mut Self {
+        self.add_op(Operation::AddIfAbsent(k, v))
+    }
+
+    pub fn upsert<F>(&mut self, k: K, default: V, modifier: F) -> &mut Self
+    where
+        F: Fn(&mut V) + Send + 'static,
+    {
+        self.add_op(Operation::Upsert(
+            k,
+            default,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ))
+    }
+
+    pub fn remove_if<F>(&mut self, k: K, predicate: F) -> &mut Self
+    where
+        F: Fn(&V) -> bool + Send + 'static,
+    {
+        self.add_op(Operation::RemoveIf(k, Predicate(Box::new(predicate))))
+    }
+
+    /// Publishes operations appended before, so preconditions see them, then validates
+    /// preconditions of the transaction and appends all of its operations.
+    pub fn commit(
+        &mut self,
+        transaction: Transaction<K, V>,
+    ) -> Result<&mut Self, TransactionError<K>> {
+        if self.has_pending() || !self.publisher.has_published() {
+            self.publish();
+        }
+        let now = Instant::now();
+        transaction.validate(&*self.handle.enter().expect("writer is alive"), now)?;
+        self.handle.extend(transaction.into_operations(now));
+        Ok(self)
+    }
+}
+
+#[derive(Clone)]
+pub struct ReadHandle<K, V> {
+    handle: left_right::ReadHandle<OrderedInner<K, V>>,
+    notifier: Arc<Notifier>,
+}
+
+impl<K, V> fmt::Debug for ReadHandle<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadHandle")
+            .field("handle", &self.handle)
+            .field("epoch", &self.epoch())
+            .finish()
+    }
+}
+
+impl<K, V> ReadHandle<K, V> {
+    pub(crate) fn new(
+        handle: left_right::ReadHandle<OrderedInner<K, V>>,
+        notifier: Arc<Notifier>,
+    ) -> Self {
+        Self { handle, notifier }
+    }
+
+    /// Returns number of publishes done by the writer so far.
+    pub fn epoch(&self) -> u64 {
+        self.notifier.epoch()
+    }
+
+    /// Blocks the current thread until the writer publishes epoch newer than `since_epoch`.
+    pub fn wait_for_publish(&self, since_epoch: u64) -> Option<u64> {
+        self.notifier.wait(since_epoch)
+    }
+
+    /// Async version of [`ReadHandle::wait_for_publish`].
+    pub fn wait_for_publish_async(
+        &self,
+        since_epoch: u64,
+    ) -> impl Future<Output = Option<u64>> + Send + 'static {
+        let notifier = self.notifier.clone();
+        std::future::poll_fn(move |cx| notifier.poll_wait(since_epoch, cx))
+    }
+}
+
+impl<K, V> ReadHandle<K, V>
+where
+    K: Ord,
+{
+    pub fn enter(&self) -> Option<MapReadRef<'_, K, V>> {
+        let guard = self.handle.enter()?;
+        if !guard.ready {
+            return None;
+        }
+        Some(MapReadRef { guard })
+    }
+
+    pub fn get<'rh, Q>(&'rh self, key: &'_ Q) -> Option<ReadGuard<'rh, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        let inner = self.handle.enter()?;
+        if !inner.ready {
+            return None;
+        }
+        ReadGuard::try_map(inner, |inner| inner.data.get(key))
+    }
+
+    pub fn len(&self) -> usize {
+        self.enter().map_or(0, |x| x.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.enter().is_none_or(|x| x.is_empty())
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.enter().is_some_and(|x| x.contains_key(key))
+    }
+}
+
+/// Read view of the ordered map. Iteration goes in ascending order of keys.
+pub struct MapReadRef<'rh, K, V> {
+    guard: ReadGuard<'rh, OrderedInner<K, V>>,
+}
+
+impl<'rh, K, V> MapReadRef<'rh, K, V>
+where
+    K: Ord,
+{
+    pub fn iter(&self) -> btree_map::Iter<'_, K, V> {
+        self.guard.data.iter()
+    }
+
+    pub fn keys(&self) -> btree_map::Keys<'_, K, V> {
+        self.guard.data.keys()
+    }
+
+    pub fn values(&self) -> btree_map::Values<'_, K, V> {
+        self.guard.data.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.guard.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.guard.data.is_empty()
+    }
+
+    pub fn get<'a, Q>(&'a self, key: &'_ Q) -> Option<&'a V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.guard.data.get(key)
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+    {
+        self.guard.data.contains_key(key)
+    }
+
+    /// Iterates over entries with keys within `range`, in ascending order.
+    pub fn range<Q, R>(&self, range: R) -> btree_map::Range<'_, K, V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Ord,
+        R: RangeBounds<Q>,
+    {
+        self.guard.data.range(range)
+    }
+
+    /// Returns entry with the smallest key.
+    pub fn first(&self) -> Option<(&K, &V)> {
+        self.guard.data.first_key_value()
+    }
+
+    /// Returns entry with the largest key.
+    pub fn last(&self) -> Option<(&K, &V)> {
+        self.guard.data.last_key_value()
+    }
+}
+
+impl<'rg, 'rh, K, V> IntoIterator for &'rg MapReadRef<'rh, K, V>
+where
+    K: Ord,
+{
+    type Item = (&'rg K, &'rg V);
+    type IntoIter = btree_map::Iter<'rg, K, V>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
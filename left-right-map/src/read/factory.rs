@@ -0,0 +1,132 @@
+use crate::epoch::Notifier;
+use crate::inner::Inner;
+use crate::read::hold::HoldMonitor;
+use crate::read::ReadHandle;
+use std::any::Any;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Produces new [`ReadHandle`]s of the same map. Unlike the handle itself, factory is `Sync`,
+/// so it can be shared behind an `Arc` by many threads or tasks.
+/// Producing a handle takes a lock internally, so keep the produced handles around.
+pub struct ReadHandleFactory<K, V, S>
+where
+    K: Eq + Hash,
+{
+    factory: left_right::ReadHandleFactory<Inner<K, V, S>>,
+    notifier: Arc<Notifier>,
+    holds: Option<Arc<HoldMonitor>>,
+}
+
+impl<K: Eq + Hash, V, S> Clone for ReadHandleFactory<K, V, S> {
+    fn clone(&self) -> Self {
+        Self {
+            factory: self.factory.clone(),
+            notifier: self.notifier.clone(),
+            holds: self.holds.clone(),
+        }
+    }
+}
+
+impl<K: Eq + Hash, V, S> fmt::Debug for ReadHandleFactory<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadHandleFactory")
+            .field("factory", &self.factory)
+            .finish()
+    }
+}
+
+impl<K, V, S> ReadHandleFactory<K, V, S>
+where
+    K: Eq + Hash,
+{
+    pub(super) fn new(handle: &ReadHandle<K, V, S>) -> Self {
+        Self {
+            factory: handle.handle.factory(),
+            notifier: handle.notifier.clone(),
+            holds: handle.holds.clone(),
+        }
+    }
+
+    pub fn handle(&self) -> ReadHandle<K, V, S> {
+        let mut handle = ReadHandle::new(self.factory.handle(), self.notifier.clone());
+        handle.holds = self.holds.clone();
+        handle
+    }
+}
+
+static NEXT_CACHE_ID: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    /// Read handles produced by caches on the current thread, by id of the cache.
+    static CACHED: RefCell<HashMap<u64, Box<dyn Any>>> = RefCell::new(HashMap::new());
+}
+
+/// Hands out a read handle owned by the current thread, producing it on first use.
+///
+/// Useful in async tasks, which can move between threads at every `.await`: the handle is only
+/// lent to a closure, so it never crosses an await point, and every worker thread produces
+/// its handle only once. Handles stay cached on their threads until the cache is dropped,
+/// threads other than the dropping one release them when they exit.
+pub struct ReadHandleCache<K, V, S>
+where
+    K: Eq + Hash,
+{
+    factory: ReadHandleFactory<K, V, S>,
+    id: u64,
+}
+
+impl<K: Eq + Hash, V, S> fmt::Debug for ReadHandleCache<K, V, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadHandleCache")
+            .field("factory", &self.factory)
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+impl<K, V, S> ReadHandleCache<K, V, S>
+where
+    K: Eq + Hash + 'static,
+    V: 'static,
+    S: 'static,
+{
+    pub fn new(factory: ReadHandleFactory<K, V, S>) -> Self {
+        Self {
+            factory,
+            id: NEXT_CACHE_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Calls `f` with the read handle of the current thread.
+    pub fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&ReadHandle<K, V, S>) -> R,
+    {
+        // taken out for the duration of `f`, so nested calls do not run into a borrowed cell
+        let cached = CACHED.with(|cached| cached.borrow_mut().remove(&self.id));
+        let handle = match cached {
+            Some(handle) => handle
+                .downcast::<ReadHandle<K, V, S>>()
+                .expect("cache id belongs to a single map"),
+            None => Box::new(self.factory.handle()),
+        };
+        let result = f(&handle);
+        CACHED.with(|cached| cached.borrow_mut().insert(self.id, handle));
+        result
+    }
+}
+
+impl<K, V, S> Drop for ReadHandleCache<K, V, S>
+where
+    K: Eq + Hash,
+{
+    fn drop(&mut self) {
+        // thread local can be gone already, if the cache is dropped during thread exit
+        let _ = CACHED.try_with(|cached| cached.borrow_mut().remove(&self.id));
+    }
+}
@@ -0,0 +1,83 @@
+use left_right::ReadGuard;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Counts read guards held longer than a threshold. Held guards stall `publish`,
+/// so long holds usually mean a reader, that does too much work while reading.
+#[derive(Debug)]
+pub(crate) struct HoldMonitor {
+    threshold: Duration,
+    long_holds: AtomicU64,
+}
+
+impl HoldMonitor {
+    pub(crate) fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            long_holds: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn long_holds(&self) -> u64 {
+        self.long_holds.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn start(&self) -> HoldTimer<'_> {
+        HoldTimer {
+            monitor: self,
+            since: Instant::now(),
+        }
+    }
+}
+
+/// Measures how long a guard is held, reports it to the monitor once dropped.
+#[derive(Debug)]
+pub(crate) struct HoldTimer<'m> {
+    monitor: &'m HoldMonitor,
+    since: Instant,
+}
+
+impl Drop for HoldTimer<'_> {
+    fn drop(&mut self) {
+        let held = self.since.elapsed();
+        if held > self.monitor.threshold {
+            self.monitor.long_holds.fetch_add(1, Ordering::Relaxed);
+            #[cfg(feature = "metrics")]
+            tracing::warn!(
+                target: "left_right_map::hold",
+                held_us = held.as_micros() as u64,
+                threshold_us = self.monitor.threshold.as_micros() as u64,
+                "read guard held too long, publish was stalled",
+            );
+        }
+    }
+}
+
+/// Guard of a single value, returned by [`super::ReadHandle::get_monitored`].
+/// The writer can not publish twice while the guard is alive, so drop it soon.
+pub struct ValueGuard<'rh, V> {
+    pub(super) guard: ReadGuard<'rh, V>,
+    pub(super) _timer: Option<HoldTimer<'rh>>,
+}
+
+impl<V: fmt::Debug> fmt::Debug for ValueGuard<'_, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ValueGuard").field(&*self.guard).finish()
+    }
+}
+
+impl<V> Deref for ValueGuard<'_, V> {
+    type Target = V;
+
+    fn deref(&self) -> &V {
+        &self.guard
+    }
+}
+
+impl<V> AsRef<V> for ValueGuard<'_, V> {
+    fn as_ref(&self) -> &V {
+        &self.guard
+    }
+}
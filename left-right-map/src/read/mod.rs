@@ -1,70 +1,208 @@
+mod factory;
+mod hold;
 pub mod read_ref;
 
+pub use factory::{ReadHandleCache, ReadHandleFactory};
+pub use hold::ValueGuard;
+
+use crate::epoch::Notifier;
 use crate::inner::Inner;
 use crate::read::read_ref::MapReadRef;
+use hold::HoldMonitor;
 use left_right::ReadGuard;
 use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
 use std::fmt::Formatter;
-use std::hash::Hash;
+use std::future::Future;
+use std::hash::{BuildHasher, Hash};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-#[derive(Clone)]
-pub struct ReadHandle<K, V>
+pub struct ReadHandle<K, V, S = RandomState>
 where
     K: Eq + Hash,
 {
-    pub(crate) handle: left_right::ReadHandle<Inner<K, V>>,
+    pub(crate) handle: left_right::ReadHandle<Inner<K, V, S>>,
+    notifier: Arc<Notifier>,
+    holds: Option<Arc<HoldMonitor>>,
+}
+
+impl<K: Eq + Hash, V, S> Clone for ReadHandle<K, V, S> {
+    fn clone(&self) -> Self {
+        #[cfg(feature = "metrics")]
+        self.notifier.reader_added();
+        Self {
+            handle: self.handle.clone(),
+            notifier: self.notifier.clone(),
+            holds: self.holds.clone(),
+        }
+    }
 }
 
-impl<K: Eq + Hash, V> std::fmt::Debug for ReadHandle<K, V> {
+#[cfg(feature = "metrics")]
+impl<K: Eq + Hash, V, S> Drop for ReadHandle<K, V, S> {
+    fn drop(&mut self) {
+        self.notifier.reader_dropped();
+    }
+}
+
+impl<K: Eq + Hash, V, S> std::fmt::Debug for ReadHandle<K, V, S> {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("ReadHandle")
             .field("handle", &self.handle)
+            .field("epoch", &self.epoch())
             .finish()
     }
 }
 
-impl<K, V> ReadHandle<K, V>
+impl<K, V, S> ReadHandle<K, V, S>
 where
     K: Eq + Hash,
 {
-    pub(crate) fn new(handle: left_right::ReadHandle<Inner<K, V>>) -> Self {
-        Self { handle }
+    pub(crate) fn new(
+        handle: left_right::ReadHandle<Inner<K, V, S>>,
+        notifier: Arc<Notifier>,
+    ) -> Self {
+        #[cfg(feature = "metrics")]
+        notifier.reader_added();
+        Self {
+            handle,
+            notifier,
+            holds: None,
+        }
+    }
+
+    /// Starts counting guards returned by `get_monitored` and `enter`, that are held longer than
+    /// `threshold`. With the `metrics` feature, each of them is also emitted as a `tracing`
+    /// warning with target `left_right_map::hold`.
+    /// Applies to this handle and to clones created from it afterwards, which share the count.
+    pub fn detect_long_holds(&mut self, threshold: Duration) -> &mut Self {
+        self.holds = Some(Arc::new(HoldMonitor::new(threshold)));
+        self
+    }
+
+    /// Number of guards held longer than the threshold set by
+    /// [`ReadHandle::detect_long_holds`], or 0 if detection is off.
+    pub fn long_holds(&self) -> u64 {
+        self.holds.as_ref().map_or(0, |holds| holds.long_holds())
+    }
+
+    /// Returns a factory of handles, that can be shared between threads.
+    pub fn factory(&self) -> ReadHandleFactory<K, V, S> {
+        ReadHandleFactory::new(self)
+    }
+
+    /// Returns number of publishes done by the writer so far.
+    /// Data of the returned epoch (or of a newer one) is visible to this handle.
+    pub fn epoch(&self) -> u64 {
+        self.notifier.epoch()
+    }
+
+    /// Blocks the current thread until the writer publishes epoch newer than `since_epoch`.
+    /// Returns the new epoch, or None if the writer was dropped in the meantime.
+    pub fn wait_for_publish(&self, since_epoch: u64) -> Option<u64> {
+        self.notifier.wait(since_epoch)
+    }
+
+    /// Async version of [`ReadHandle::wait_for_publish`], does not block the executor thread.
+    pub fn wait_for_publish_async(
+        &self,
+        since_epoch: u64,
+    ) -> impl Future<Output = Option<u64>> + Send + 'static {
+        let notifier = self.notifier.clone();
+        std::future::poll_fn(move |cx| notifier.poll_wait(since_epoch, cx))
     }
 }
 
-impl<K, V> ReadHandle<K, V>
+impl<K, V, S> ReadHandle<K, V, S>
 where
     K: Eq + Hash,
+    S: BuildHasher,
 {
-    pub fn enter(&self) -> Option<MapReadRef<'_, K, V>> {
+    pub fn enter(&self) -> Option<MapReadRef<'_, K, V, S>> {
+        let timer = self.holds.as_deref().map(HoldMonitor::start);
         let guard = self.handle.enter()?;
         if !guard.ready {
             return None;
         }
-        Some(MapReadRef { guard })
+        let now = (!guard.expiry.is_empty()).then(Instant::now);
+        Some(MapReadRef {
+            guard,
+            now,
+            _timer: timer,
+        })
     }
 
-    fn get_raw<Q: ?Sized>(&self, key: &Q) -> Option<ReadGuard<'_, V>>
+    fn get_raw<Q>(&self, key: &Q) -> Option<ReadGuard<'_, V>>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: ?Sized + Hash + Eq,
     {
         let inner = self.handle.enter()?;
         if !inner.ready {
             return None;
         }
+        if !inner.expiry.is_empty() && inner.is_expired(key, Instant::now()) {
+            return None;
+        }
 
         ReadGuard::try_map(inner, |inner| inner.data.get(key))
     }
 
-    pub fn get<'rh, Q: ?Sized>(&'rh self, key: &'_ Q) -> Option<ReadGuard<'rh, V>>
+    /// Returns guard of the value. Guard stalls the writer until dropped, prefer
+    /// [`ReadHandle::get_and`] or [`ReadHandle::get_cloned`] when the value is needed for longer.
+    pub fn get<'rh, Q>(&'rh self, key: &'_ Q) -> Option<ReadGuard<'rh, V>>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: ?Sized + Hash + Eq,
     {
         self.get_raw(key.borrow())
     }
 
+    /// Same as [`ReadHandle::get`], but the guard is counted by
+    /// [`ReadHandle::detect_long_holds`] if it is held for too long.
+    pub fn get_monitored<'rh, Q>(&'rh self, key: &'_ Q) -> Option<ValueGuard<'rh, V>>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        let timer = self.holds.as_deref().map(HoldMonitor::start);
+        let guard = self.get_raw(key.borrow())?;
+        Some(ValueGuard {
+            guard,
+            _timer: timer,
+        })
+    }
+
+    /// Applies `f` to the value and releases the guard right away.
+    pub fn get_and<Q, F, R>(&self, key: &Q, f: F) -> Option<R>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        F: FnOnce(&V) -> R,
+    {
+        self.get(key).map(|v| f(&v))
+    }
+
+    pub fn get_cloned<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+        V: Clone,
+    {
+        self.get_and(key, V::clone)
+    }
+
+    /// Projects every entry of the map with `f`, without holding the guard afterwards.
+    /// Returns empty vector if the map is not ready yet.
+    pub fn map_values<F, R>(&self, mut f: F) -> Vec<R>
+    where
+        F: FnMut(&K, &V) -> R,
+    {
+        self.enter()
+            .map_or_else(Vec::new, |map| map.iter().map(|(k, v)| f(k, v)).collect())
+    }
+
     /// Returns the number of non-empty keys present in the map.
     pub fn len(&self) -> usize {
         self.enter().map_or(0, |x| x.len())
@@ -72,14 +210,14 @@ where
 
     /// Returns true if the map contains no elements.
     pub fn is_empty(&self) -> bool {
-        self.enter().map_or(true, |x| x.is_empty())
+        self.enter().is_none_or(|x| x.is_empty())
     }
 
-    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: ?Sized + Hash + Eq,
     {
-        self.enter().map_or(false, |x| x.contains_key(key))
+        self.enter().is_some_and(|x| x.contains_key(key))
     }
 }
@@ -1,77 +1,106 @@
 use crate::inner::Inner;
+use crate::read::hold::HoldTimer;
 use left_right::ReadGuard;
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::collections::hash_map::{self, RandomState};
 use std::fmt;
 use std::hash::{BuildHasher, Hash};
+use std::time::Instant;
 
-pub struct MapReadRef<'rh, K, V>
+/// Read view of the map. Expiry of entries is checked against the time the view was entered,
+/// so entries expiring while the view is held stay visible through it.
+pub struct MapReadRef<'rh, K, V, S = RandomState>
 where
     K: Eq + Hash,
 {
-    pub(super) guard: ReadGuard<'rh, Inner<K, V>>,
+    pub(super) guard: ReadGuard<'rh, Inner<K, V, S>>,
+    /// Set only if the map has entries with TTL.
+    pub(super) now: Option<Instant>,
+    /// Only held for its drop, which reports how long the guard was alive.
+    pub(super) _timer: Option<HoldTimer<'rh>>,
 }
 
-impl<'rh, K, V> MapReadRef<'rh, K, V>
+impl<'rh, K, V, S> MapReadRef<'rh, K, V, S>
 where
     K: Hash + Eq,
+    S: BuildHasher,
 {
-    pub fn iter(&self) -> ReadGuardIter<'_, K, V> {
+    pub fn iter(&self) -> ReadGuardIter<'_, K, V, S> {
         ReadGuardIter {
             iter: self.guard.data.iter(),
+            inner: &self.guard,
+            now: self.now,
         }
     }
 
     pub fn len(&self) -> usize {
-        self.guard.data.len()
+        let expired = match self.now {
+            Some(now) => self.guard.expiry.values().filter(|at| **at <= now).count(),
+            None => 0,
+        };
+        self.guard.data.len() - expired
     }
 
     pub fn is_empty(&self) -> bool {
-        self.guard.data.is_empty()
+        self.len() == 0
     }
 
-    pub fn get<'a, Q: ?Sized>(&'a self, key: &'_ Q) -> Option<&'a V>
+    fn is_live<Q>(&self, key: &Q) -> bool
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: ?Sized + Hash + Eq,
     {
-        self.guard.data.get(key)
+        self.now.is_none_or(|now| !self.guard.is_expired(key, now))
     }
 
-    pub fn contains_key<Q: ?Sized>(&self, key: &Q) -> bool
+    pub fn get<'a, Q>(&'a self, key: &'_ Q) -> Option<&'a V>
     where
         K: Borrow<Q>,
-        Q: Hash + Eq,
+        Q: ?Sized + Hash + Eq,
     {
-        self.guard.data.contains_key(key)
+        self.guard.data.get(key).filter(|_| self.is_live(key))
     }
 
-    pub fn values(&self) -> ValuesIter<'_, K, V> {
-        ValuesIter {
-            iter: self.guard.data.iter(),
-        }
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.guard.data.contains_key(key) && self.is_live(key)
+    }
+
+    pub fn keys(&self) -> KeysIter<'_, K, V, S> {
+        KeysIter { iter: self.iter() }
+    }
+
+    pub fn values(&self) -> ValuesIter<'_, K, V, S> {
+        ValuesIter { iter: self.iter() }
     }
 }
 
-impl<'rg, 'rh, K, V> IntoIterator for &'rg MapReadRef<'rh, K, V>
+impl<'rg, 'rh, K, V, S> IntoIterator for &'rg MapReadRef<'rh, K, V, S>
 where
     K: Eq + Hash,
+    S: BuildHasher,
 {
     type Item = (&'rg K, &'rg V);
-    type IntoIter = ReadGuardIter<'rg, K, V>;
+    type IntoIter = ReadGuardIter<'rg, K, V, S>;
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
 
-pub struct ReadGuardIter<'rg, K, V>
+/// Iterator over entries of the map, skips expired ones.
+pub struct ReadGuardIter<'rg, K, V, S = RandomState>
 where
     K: Eq + Hash,
 {
-    iter: <&'rg HashMap<K, V> as IntoIterator>::IntoIter,
+    iter: hash_map::Iter<'rg, K, V>,
+    inner: &'rg Inner<K, V, S>,
+    now: Option<Instant>,
 }
 
-impl<'rg, K, V> fmt::Debug for ReadGuardIter<'rg, K, V>
+impl<'rg, K, V, S> fmt::Debug for ReadGuardIter<'rg, K, V, S>
 where
     K: Eq + Hash + fmt::Debug,
     V: fmt::Debug,
@@ -81,24 +110,59 @@ where
     }
 }
 
-impl<'rg, K, V> Iterator for ReadGuardIter<'rg, K, V>
+impl<'rg, K, V, S> Iterator for ReadGuardIter<'rg, K, V, S>
 where
     K: Eq + Hash,
+    S: BuildHasher,
 {
     type Item = (&'rg K, &'rg V);
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next()
+        match self.now {
+            Some(now) => {
+                let inner = self.inner;
+                self.iter.find(|(k, _)| !inner.is_expired(*k, now))
+            }
+            None => self.iter.next(),
+        }
+    }
+}
+
+pub struct KeysIter<'rg, K, V, S = RandomState>
+where
+    K: Eq + Hash,
+{
+    iter: ReadGuardIter<'rg, K, V, S>,
+}
+
+impl<'rg, K, V, S> fmt::Debug for KeysIter<'rg, K, V, S>
+where
+    K: Eq + Hash + fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("KeysIter").field(&self.iter).finish()
+    }
+}
+
+impl<'rg, K, V, S> Iterator for KeysIter<'rg, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    type Item = &'rg K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(k, _)| k)
     }
 }
 
-pub struct ValuesIter<'rg, K, V>
+pub struct ValuesIter<'rg, K, V, S = RandomState>
 where
     K: Eq + Hash,
 {
-    iter: <&'rg HashMap<K, V> as IntoIterator>::IntoIter,
+    iter: ReadGuardIter<'rg, K, V, S>,
 }
 
-impl<'rg, K, V> fmt::Debug for ValuesIter<'rg, K, V>
+impl<'rg, K, V, S> fmt::Debug for ValuesIter<'rg, K, V, S>
 where
     K: Eq + Hash + fmt::Debug,
     V: fmt::Debug,
@@ -108,9 +172,10 @@ where
     }
 }
 
-impl<'rg, K, V> Iterator for ValuesIter<'rg, K, V>
+impl<'rg, K, V, S> Iterator for ValuesIter<'rg, K, V, S>
 where
     K: Eq + Hash,
+    S: BuildHasher,
 {
     type Item = &'rg V;
     fn next(&mut self) -> Option<Self::Item> {
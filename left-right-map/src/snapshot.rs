@@ -0,0 +1,172 @@
+use crate::inner::Inner;
+use serde::de::DeserializeOwned;
+use serde::{Serialize, Serializer};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MAGIC: [u8; 4] = *b"LRMS";
+/// Bump whenever the encoding of the snapshot changes, so old snapshots get rejected.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Entries of the map, that are not expired at `now`.
+struct Live<'a, K, V, S>
+where
+    K: Eq + Hash,
+{
+    inner: &'a Inner<K, V, S>,
+    now: Instant,
+}
+
+impl<K, V, S> Serialize for Live<'_, K, V, S>
+where
+    K: Eq + Hash + Serialize,
+    V: Serialize,
+    S: BuildHasher,
+{
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let Live { inner, now } = *self;
+        serializer.collect_map(
+            inner
+                .data
+                .iter()
+                .filter(|(k, _)| !inner.is_expired(*k, now)),
+        )
+    }
+}
+
+/// Content of a snapshot, deadlines of entries are restored relative to the current time.
+pub(crate) struct Content<K, V> {
+    pub(crate) data: HashMap<K, V>,
+    pub(crate) expiry: Vec<(K, Instant)>,
+}
+
+/// Writes header followed by CBOR encoded content of the map. Expired entries are left out,
+/// deadlines of the others are kept as milliseconds since the unix epoch.
+/// `seq` is sequence number of the first operation of the write-ahead log not included in the
+/// snapshot, or 0 if the map is not logged.
+pub(crate) fn write<K, V, S, W>(
+    inner: &Inner<K, V, S>,
+    seq: u64,
+    mut writer: W,
+) -> Result<(), SnapshotError>
+where
+    K: Eq + Hash + Serialize,
+    V: Serialize,
+    S: BuildHasher,
+    W: Write,
+{
+    let now = Instant::now();
+    let expiry: Vec<(&K, u64)> = inner
+        .expiry
+        .iter()
+        .filter(|(_, at)| **at > now)
+        .map(|(key, at)| (key, unix_millis(*at)))
+        .collect();
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+    writer.write_all(&seq.to_le_bytes())?;
+    ciborium::into_writer(&(Live { inner, now }, expiry), &mut writer)
+        .map_err(|err| SnapshotError::Encode(err.to_string()))?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub(crate) fn read<K, V, R>(mut reader: R) -> Result<(u64, Content<K, V>), SnapshotError>
+where
+    K: Eq + Hash + DeserializeOwned,
+    V: DeserializeOwned,
+    R: Read,
+{
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(SnapshotError::InvalidHeader);
+    }
+
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let mut seq = [0u8; 8];
+    reader.read_exact(&mut seq)?;
+
+    let (data, expiry): (HashMap<K, V>, Vec<(K, u64)>) =
+        ciborium::from_reader(reader).map_err(|err| SnapshotError::Decode(err.to_string()))?;
+    let expiry = expiry
+        .into_iter()
+        .map(|(key, millis)| (key, from_unix_millis(millis)))
+        .collect();
+    Ok((u64::from_le_bytes(seq), Content { data, expiry }))
+}
+
+/// Converts `at` to wall-clock time in milliseconds since the unix epoch, so it survives
+/// a restart of the process.
+pub(crate) fn unix_millis(at: Instant) -> u64 {
+    let (now, wall) = (Instant::now(), SystemTime::now());
+    let at = match at.checked_duration_since(now) {
+        Some(left) => wall + left,
+        None => wall.checked_sub(now - at).unwrap_or(UNIX_EPOCH),
+    };
+    at.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Inverse of [`unix_millis`]. Times too far in the past for `Instant` are clamped to now,
+/// order of times is preserved either way.
+pub(crate) fn from_unix_millis(millis: u64) -> Instant {
+    let (now, wall) = (Instant::now(), SystemTime::now());
+    match (UNIX_EPOCH + Duration::from_millis(millis)).duration_since(wall) {
+        Ok(left) => now + left,
+        Err(err) => now.checked_sub(err.duration()).unwrap_or(now),
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// Input does not start with the snapshot header at all.
+    InvalidHeader,
+    /// Snapshot was written by an incompatible version, which is carried by the variant.
+    UnsupportedVersion(u32),
+    Encode(String),
+    Decode(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Io(err) => write!(f, "snapshot io error: {}", err),
+            SnapshotError::InvalidHeader => write!(f, "input is not a left-right-map snapshot"),
+            SnapshotError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported snapshot version {}, expected {}",
+                version, SNAPSHOT_VERSION
+            ),
+            SnapshotError::Encode(err) => write!(f, "failed to encode snapshot: {}", err),
+            SnapshotError::Decode(err) => write!(f, "failed to decode snapshot: {}", err),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SnapshotError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(err: io::Error) -> Self {
+        SnapshotError::Io(err)
+    }
+}
@@ -0,0 +1,7 @@
+//! Locks shared between threads, swapped for loom's under `--cfg loom`,
+//! so loom models can schedule threads around them.
+
+#[cfg(loom)]
+pub(crate) use loom::sync::{Condvar, Mutex, MutexGuard};
+#[cfg(not(loom))]
+pub(crate) use std::sync::{Condvar, Mutex, MutexGuard};
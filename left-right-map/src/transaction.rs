@@ -0,0 +1,195 @@
+use crate::inner::Store;
+use crate::write::{Modifier, Operation, Predicate};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::time::Instant;
+
+/// Group of operations, that are appended to the writer as a single unit.
+/// Preconditions are checked when the transaction is committed. Either all preconditions hold
+/// and all operations are appended, or nothing is.
+///
+/// [`crate::write::WriteHandle`] with an enabled view checks preconditions against the view,
+/// including operations not yet published. Other writers publish pending operations before
+/// checking them, so both copies of the map apply the transaction to the same content.
+/// Expired entries count as missing, even if they were not swept yet.
+pub struct Transaction<K, V> {
+    preconditions: Vec<Precondition<K, V>>,
+    operations: Vec<Operation<K, V, Infallible>>,
+}
+
+impl<K, V> Default for Transaction<K, V> {
+    fn default() -> Self {
+        Self {
+            preconditions: Vec::new(),
+            operations: Vec::new(),
+        }
+    }
+}
+
+impl<K, V> fmt::Debug for Transaction<K, V>
+where
+    K: fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("preconditions", &self.preconditions)
+            .field("operations", &self.operations)
+            .finish()
+    }
+}
+
+impl<K, V> Transaction<K, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    pub fn require_present(mut self, k: K) -> Self {
+        self.preconditions.push(Precondition::Present(k));
+        self
+    }
+
+    pub fn require_absent(mut self, k: K) -> Self {
+        self.preconditions.push(Precondition::Absent(k));
+        self
+    }
+
+    /// Requires the key to be present and `predicate` to return true for its value.
+    pub fn require<F>(mut self, k: K, predicate: F) -> Self
+    where
+        F: Fn(&V) -> bool + Send + 'static,
+    {
+        self.preconditions
+            .push(Precondition::Matches(k, Predicate(Box::new(predicate))));
+        self
+    }
+
+    pub fn insert(mut self, k: K, v: V) -> Self {
+        self.operations.push(Operation::Add(k, v));
+        self
+    }
+
+    pub fn remove(mut self, k: K) -> Self {
+        self.operations.push(Operation::Remove(k));
+        self
+    }
+
+    pub fn update(mut self, k: K, v: V) -> Self {
+        self.operations
+            .push(Operation::Replace(k, v, Instant::now()));
+        self
+    }
+
+    pub fn modify<F>(mut self, k: K, modifier: F) -> Self
+    where
+        F: Fn(&mut V) + Send + 'static,
+    {
+        self.operations.push(Operation::Modify(
+            k,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ));
+        self
+    }
+
+    /// Moves value stored under `from` to `to`, overwriting whatever is stored under `to`.
+    pub fn move_value(mut self, from: K, to: K) -> Self {
+        self.operations.push(Operation::Move(from, to));
+        self
+    }
+
+    /// Checks preconditions against `data`, where entries expired at `now` count as missing,
+    /// the same way readers and [`crate::view::WriterView`] see them.
+    pub(crate) fn validate<M>(&self, data: &M, now: Instant) -> Result<(), TransactionError<K>>
+    where
+        K: Clone,
+        M: Store<K, V>,
+    {
+        let get = |k: &K| {
+            data.get(k)
+                .filter(|_| data.expiry(k).is_none_or(|at| at > now))
+        };
+        for precondition in &self.preconditions {
+            match precondition {
+                Precondition::Present(k) if get(k).is_none() => {
+                    return Err(TransactionError::KeyMissing(k.clone()));
+                }
+                Precondition::Absent(k) if get(k).is_some() => {
+                    return Err(TransactionError::KeyPresent(k.clone()));
+                }
+                Precondition::Matches(k, predicate) => match get(k) {
+                    None => return Err(TransactionError::KeyMissing(k.clone())),
+                    Some(v) if !predicate.test(v) => {
+                        return Err(TransactionError::PredicateFailed(k.clone()));
+                    }
+                    Some(_) => {}
+                },
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the operations with updates timed at `now`, the time preconditions were
+    /// checked at, so updates treat the same entries as expired as preconditions do.
+    pub(crate) fn into_operations<D>(self, now: Instant) -> Vec<Operation<K, V, D>> {
+        let timed = |op| match op {
+            Operation::Replace(k, v, _) => Operation::Replace(k, v, now),
+            Operation::Modify(k, modifier, _) => Operation::Modify(k, modifier, now),
+            op => op,
+        };
+        self.operations
+            .into_iter()
+            .map(timed)
+            .map(Operation::cast)
+            .collect()
+    }
+}
+
+enum Precondition<K, V> {
+    Present(K),
+    Absent(K),
+    Matches(K, Predicate<V>),
+}
+
+impl<K, V> fmt::Debug for Precondition<K, V>
+where
+    K: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Precondition::Present(ref a) => f.debug_tuple("Present").field(a).finish(),
+            Precondition::Absent(ref a) => f.debug_tuple("Absent").field(a).finish(),
+            Precondition::Matches(ref a, ref b) => {
+                f.debug_tuple("Matches").field(a).field(b).finish()
+            }
+        }
+    }
+}
+
+/// Precondition of a [`Transaction`], that did not hold, together with its key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionError<K> {
+    KeyMissing(K),
+    KeyPresent(K),
+    PredicateFailed(K),
+}
+
+impl<K: fmt::Debug> fmt::Display for TransactionError<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionError::KeyMissing(k) => write!(f, "required key {:?} is missing", k),
+            TransactionError::KeyPresent(k) => write!(f, "key {:?} is already present", k),
+            TransactionError::PredicateFailed(k) => {
+                write!(f, "value of key {:?} did not match the predicate", k)
+            }
+        }
+    }
+}
+
+impl<K: fmt::Debug> Error for TransactionError<K> {}
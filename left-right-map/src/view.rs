@@ -0,0 +1,370 @@
+//! Read-your-writes view of the writer, see [`crate::write::WriteHandle::view`].
+//!
+//! Pending operations are not applied to any copy of the map until publish, so the writer keeps
+//! an overlay with their effects on top of the published copy. Every appended operation is also
+//! applied to the overlay, the same way it is applied to the copies, so the view follows exactly
+//! the semantics of operations. Eviction of bounded maps is the exception, entries over capacity
+//! disappear from the view only once published.
+
+use crate::inner::{Inner, Store};
+use crate::transaction::{Transaction, TransactionError};
+use crate::write::MapOperation;
+use left_right::ReadGuard;
+use std::borrow::Borrow;
+use std::collections::hash_map::{self, RandomState};
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hash};
+use std::time::Instant;
+
+#[derive(Debug, Clone)]
+struct Pending<V> {
+    value: V,
+    expiry: Option<Instant>,
+}
+
+impl<V> Pending<V> {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expiry.is_none_or(|at| at > now)
+    }
+}
+
+/// Effects of operations appended since the last publish.
+#[derive(Debug)]
+pub(crate) struct Overlay<K, V, S = RandomState> {
+    /// Changed keys, `None` for removed ones.
+    entries: HashMap<K, Option<Pending<V>>, S>,
+    /// Whether the published content was dropped as a whole, e.g. by purge.
+    cleared: bool,
+}
+
+impl<K, V, S> Overlay<K, V, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    pub(crate) fn with_hasher(hasher: S) -> Self {
+        Self {
+            entries: HashMap::with_hasher(hasher),
+            cleared: false,
+        }
+    }
+
+    /// Applies the operation on top of `published`, which must be the published copy of the map.
+    pub(crate) fn apply(&mut self, op: &mut MapOperation<K, V, S>, published: &Inner<K, V, S>) {
+        op.apply_first(&mut OverlayStore {
+            overlay: self,
+            published,
+        });
+    }
+
+    /// Validates the transaction against the overlaid content and applies its operations.
+    pub(crate) fn commit(
+        &mut self,
+        transaction: Transaction<K, V>,
+        published: &Inner<K, V, S>,
+    ) -> Result<Vec<MapOperation<K, V, S>>, TransactionError<K>> {
+        let mut store = OverlayStore {
+            overlay: self,
+            published,
+        };
+        let now = Instant::now();
+        transaction.validate(&store, now)?;
+        let mut operations = transaction.into_operations(now);
+        for op in &mut operations {
+            op.apply_first(&mut store);
+        }
+        Ok(operations)
+    }
+
+    /// Forgets all operations, once they are published.
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.cleared = false;
+    }
+}
+
+/// Overlay together with the published copy it overlays, seen as a copy of the map
+/// with pending operations applied.
+struct OverlayStore<'a, K, V, S>
+where
+    K: Eq + Hash,
+{
+    overlay: &'a mut Overlay<K, V, S>,
+    published: &'a Inner<K, V, S>,
+}
+
+impl<K, V, S> OverlayStore<'_, K, V, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    /// Published entry, unless the overlay shadows it.
+    fn published(&self, key: &K) -> Option<Pending<V>> {
+        if self.overlay.cleared || self.overlay.entries.contains_key(key) {
+            return None;
+        }
+        let value = self.published.data.get(key)?;
+        Some(Pending {
+            value: value.clone(),
+            expiry: self.published.expiry.get(key).copied(),
+        })
+    }
+
+    fn entry_mut(&mut self, key: &K) -> Option<&mut Pending<V>> {
+        if let Some(pending) = self.published(key) {
+            self.overlay.entries.insert(key.clone(), Some(pending));
+        }
+        self.overlay.entries.get_mut(key)?.as_mut()
+    }
+
+    /// Removes all published entries not shadowed by the overlay, for which `remove` is true.
+    fn remove_published(&mut self, remove: impl Fn(&K, &V) -> bool) {
+        if self.overlay.cleared {
+            return;
+        }
+        for (key, value) in &self.published.data {
+            if !self.overlay.entries.contains_key(key) && remove(key, value) {
+                self.overlay.entries.insert(key.clone(), None);
+            }
+        }
+    }
+}
+
+impl<K, V, S> Store<K, V> for OverlayStore<'_, K, V, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher,
+{
+    fn get(&self, key: &K) -> Option<&V> {
+        match self.overlay.entries.get(key) {
+            Some(pending) => pending.as_ref().map(|p| &p.value),
+            None if self.overlay.cleared => None,
+            None => self.published.data.get(key),
+        }
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        self.entry_mut(key).map(|p| &mut p.value)
+    }
+
+    fn insert(&mut self, key: K, value: V) {
+        let pending = Pending {
+            value,
+            expiry: None,
+        };
+        self.overlay.entries.insert(key, Some(pending));
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let removed = match self.overlay.entries.insert(key.clone(), None) {
+            Some(pending) => pending,
+            None if self.overlay.cleared => None,
+            None => self.published.data.get(key).cloned().map(|value| Pending {
+                value,
+                expiry: None,
+            }),
+        };
+        removed.map(|p| p.value)
+    }
+
+    fn clear(&mut self) {
+        self.overlay.entries.clear();
+        self.overlay.cleared = true;
+    }
+
+    fn retain(&mut self, predicate: &dyn Fn(&K, &V) -> bool) {
+        for (key, pending) in self.overlay.entries.iter_mut() {
+            if pending.as_ref().is_some_and(|p| !predicate(key, &p.value)) {
+                *pending = None;
+            }
+        }
+        self.remove_published(|key, value| !predicate(key, value));
+    }
+
+    fn reserve(&mut self, _additional: usize) {}
+
+    fn mark_ready(&mut self) {}
+
+    fn expiry(&self, key: &K) -> Option<Instant> {
+        match self.overlay.entries.get(key) {
+            Some(pending) => pending.as_ref().and_then(|p| p.expiry),
+            None if self.overlay.cleared => None,
+            None => self.published.expiry.get(key).copied(),
+        }
+    }
+
+    fn set_expiry(&mut self, key: K, at: Instant) {
+        if let Some(pending) = self.entry_mut(&key) {
+            pending.expiry = Some(at);
+        }
+    }
+
+    fn remove_expired(&mut self, now: Instant) {
+        for pending in self.overlay.entries.values_mut() {
+            if pending.as_ref().is_some_and(|p| !p.is_live(now)) {
+                *pending = None;
+            }
+        }
+        let expiry = &self.published.expiry;
+        self.remove_published(|key, _| expiry.get(key).is_some_and(|at| *at <= now));
+    }
+
+    fn report_missed(&mut self, _enabled: bool) {}
+
+    fn missed(&mut self, _key: &K) {}
+
+    type Data = HashMap<K, V, S>;
+
+    fn replace_all(&mut self, data: HashMap<K, V, S>) {
+        self.clear();
+        for (key, value) in data {
+            self.insert(key, value);
+        }
+    }
+}
+
+/// Content of the map as the writer sees it: published entries with pending operations
+/// applied. Like readers, the view skips expired entries, checked against the time
+/// the view was created.
+pub struct WriterView<'w, K, V, S = RandomState>
+where
+    K: Eq + Hash,
+{
+    published: ReadGuard<'w, Inner<K, V, S>>,
+    overlay: &'w Overlay<K, V, S>,
+    now: Instant,
+}
+
+impl<K, V, S> fmt::Debug for WriterView<'_, K, V, S>
+where
+    K: Eq + Hash + fmt::Debug,
+    V: fmt::Debug,
+    S: BuildHasher,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<'w, K, V, S> WriterView<'w, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    pub(crate) fn new(
+        published: ReadGuard<'w, Inner<K, V, S>>,
+        overlay: &'w Overlay<K, V, S>,
+    ) -> Self {
+        Self {
+            published,
+            overlay,
+            now: Instant::now(),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        match self.overlay.entries.get(key) {
+            Some(pending) => pending
+                .as_ref()
+                .filter(|p| p.is_live(self.now))
+                .map(|p| &p.value),
+            None if self.overlay.cleared => None,
+            None => self
+                .published
+                .data
+                .get(key)
+                .filter(|_| !self.published.is_expired(key, self.now)),
+        }
+    }
+
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: ?Sized + Hash + Eq,
+    {
+        self.get(key).is_some()
+    }
+
+    /// Counts the entries, takes time linear in the size of the map.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    pub fn iter(&self) -> WriterViewIter<'_, K, V, S> {
+        let published = (!self.overlay.cleared).then(|| self.published.data.iter());
+        WriterViewIter {
+            pending: self.overlay.entries.iter(),
+            published,
+            view: self,
+        }
+    }
+}
+
+impl<'v, K, V, S> IntoIterator for &'v WriterView<'_, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    type Item = (&'v K, &'v V);
+    type IntoIter = WriterViewIter<'v, K, V, S>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over entries of [`WriterView`], pending ones first.
+pub struct WriterViewIter<'v, K, V, S = RandomState>
+where
+    K: Eq + Hash,
+{
+    pending: hash_map::Iter<'v, K, Option<Pending<V>>>,
+    /// `None` if the published content was cleared.
+    published: Option<hash_map::Iter<'v, K, V>>,
+    view: &'v WriterView<'v, K, V, S>,
+}
+
+impl<K, V, S> fmt::Debug for WriterViewIter<'_, K, V, S>
+where
+    K: Eq + Hash + fmt::Debug,
+    V: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriterViewIter")
+            .field("pending", &self.pending)
+            .field("published", &self.published)
+            .finish()
+    }
+}
+
+impl<'v, K, V, S> Iterator for WriterViewIter<'v, K, V, S>
+where
+    K: Eq + Hash,
+    S: BuildHasher,
+{
+    type Item = (&'v K, &'v V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = self.view.now;
+        for (key, pending) in self.pending.by_ref() {
+            match pending {
+                Some(p) if p.is_live(now) => return Some((key, &p.value)),
+                _ => {}
+            }
+        }
+        let view = self.view;
+        self.published.as_mut()?.find(|(key, _)| {
+            !view.overlay.entries.contains_key(*key) && !view.published.is_expired(*key, now)
+        })
+    }
+}
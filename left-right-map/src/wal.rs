@@ -0,0 +1,487 @@
+//! Write-ahead log of writer operations.
+//!
+//! Every operation appended through [`LoggedWriteHandle`] is written to an append-only file
+//! before it reaches the map, so it survives a restart even if it was never published.
+//! Closures can not be serialized, so modifiers and predicates have to be registered under
+//! a name in the [`Registry`] and referenced by that name, both when logging and when replaying.
+
+use crate::read::ReadHandle;
+use crate::snapshot::{self, from_unix_millis, unix_millis, SnapshotError};
+use crate::write::{Filter, MapOperation, Modifier, Operation, Predicate, WriteHandle};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const MAGIC: [u8; 4] = *b"LRMW";
+/// Bump whenever the encoding of the log changes, so old logs get rejected.
+pub const LOG_VERSION: u32 = 1;
+
+type NamedModifier<V> = Arc<dyn Fn(&mut V) + Send + Sync>;
+type NamedPredicate<V> = Arc<dyn Fn(&V) -> bool + Send + Sync>;
+type LoggedHandles<K, V> = (LoggedWriteHandle<K, V>, ReadHandle<K, V>);
+
+/// Named modifiers and predicates, that can be used by logged operations.
+/// The same registry has to be provided when the log is replayed.
+pub struct Registry<V> {
+    modifiers: HashMap<String, NamedModifier<V>>,
+    predicates: HashMap<String, NamedPredicate<V>>,
+}
+
+impl<V> Default for Registry<V> {
+    fn default() -> Self {
+        Self {
+            modifiers: HashMap::new(),
+            predicates: HashMap::new(),
+        }
+    }
+}
+
+impl<V> fmt::Debug for Registry<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("modifiers", &self.modifiers.keys())
+            .field("predicates", &self.predicates.keys())
+            .finish()
+    }
+}
+
+impl<V> Registry<V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_modifier<F>(mut self, name: impl Into<String>, modifier: F) -> Self
+    where
+        F: Fn(&mut V) + Send + Sync + 'static,
+    {
+        self.modifiers.insert(name.into(), Arc::new(modifier));
+        self
+    }
+
+    pub fn with_predicate<F>(mut self, name: impl Into<String>, predicate: F) -> Self
+    where
+        F: Fn(&V) -> bool + Send + Sync + 'static,
+    {
+        self.predicates.insert(name.into(), Arc::new(predicate));
+        self
+    }
+
+    fn modifier(&self, name: &str) -> Result<Modifier<V>, WalError>
+    where
+        V: 'static,
+    {
+        let modifier = self
+            .modifiers
+            .get(name)
+            .ok_or_else(|| WalError::UnknownModifier(name.to_string()))?
+            .clone();
+        Ok(Modifier(Box::new(move |v| modifier(v))))
+    }
+
+    fn predicate(&self, name: &str) -> Result<Predicate<V>, WalError>
+    where
+        V: 'static,
+    {
+        let predicate = self
+            .predicates
+            .get(name)
+            .ok_or_else(|| WalError::UnknownPredicate(name.to_string()))?
+            .clone();
+        Ok(Predicate(Box::new(move |v| predicate(v))))
+    }
+}
+
+/// Serializable form of an [`Operation`], closures are replaced by their registered names
+/// and instants by wall-clock milliseconds since the unix epoch.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "K: Eq + Hash + Deserialize<'de>, V: Deserialize<'de>"))]
+enum Record<K, V> {
+    Add(K, V),
+    AddWithTtl(K, V, u64),
+    AddIfAbsent(K, V),
+    Replace(K, V, u64),
+    ReplaceOrAdd(K, V, u64),
+    Upsert(K, V, String, u64),
+    Remove(K),
+    RemoveIf(K, String),
+    Modify(K, String, u64),
+    Retain(String),
+    Extend(Vec<(K, V)>),
+    ReplaceAll(HashMap<K, V>),
+    Purge,
+    Sweep(u64),
+}
+
+impl<K, V: 'static> Record<K, V> {
+    fn into_operation(
+        self,
+        registry: &Registry<V>,
+    ) -> Result<MapOperation<K, V, RandomState>, WalError> {
+        Ok(match self {
+            Record::Add(k, v) => Operation::Add(k, v),
+            Record::AddWithTtl(k, v, at) => Operation::AddWithTtl(k, v, from_unix_millis(at)),
+            Record::AddIfAbsent(k, v) => Operation::AddIfAbsent(k, v),
+            Record::Replace(k, v, now) => Operation::Replace(k, v, from_unix_millis(now)),
+            Record::ReplaceOrAdd(k, v, now) => Operation::ReplaceOrAdd(k, v, from_unix_millis(now)),
+            Record::Upsert(k, v, name, now) => {
+                Operation::Upsert(k, v, registry.modifier(&name)?, from_unix_millis(now))
+            }
+            Record::Remove(k) => Operation::Remove(k),
+            Record::RemoveIf(k, name) => Operation::RemoveIf(k, registry.predicate(&name)?),
+            Record::Modify(k, name, now) => {
+                Operation::Modify(k, registry.modifier(&name)?, from_unix_millis(now))
+            }
+            Record::Retain(name) => {
+                let predicate = registry.predicate(&name)?;
+                Operation::Retain(Filter(Box::new(move |_, v| predicate.test(v))))
+            }
+            Record::Extend(entries) => Operation::Extend(entries),
+            Record::ReplaceAll(entries) => Operation::ReplaceAll(entries),
+            Record::Purge => Operation::Purge,
+            Record::Sweep(now) => Operation::Sweep(from_unix_millis(now)),
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(bound(deserialize = "K: Eq + Hash + Deserialize<'de>, V: Deserialize<'de>"))]
+struct Entry<K, V> {
+    seq: u64,
+    record: Record<K, V>,
+}
+
+/// Writer, that logs every operation before appending it to the map.
+///
+/// Operations are written to the log file as they are appended, and the file is synced to disk
+/// on every publish. Operations with closures are only available in their named form.
+pub struct LoggedWriteHandle<K, V>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+{
+    handle: WriteHandle<K, V>,
+    log: File,
+    registry: Registry<V>,
+    // sequence number of the next logged operation
+    seq: u64,
+}
+
+impl<K, V> fmt::Debug for LoggedWriteHandle<K, V>
+where
+    K: Eq + Hash + Clone + fmt::Debug,
+    V: fmt::Debug + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LoggedWriteHandle")
+            .field("handle", &self.handle)
+            .field("log", &self.log)
+            .field("registry", &self.registry)
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
+
+/// Opens the log at `path` (creating it if missing) and replays all of its operations
+/// into a new, published map.
+pub fn open<K, V, P>(path: P, registry: Registry<V>) -> Result<LoggedHandles<K, V>, WalError>
+where
+    K: Eq + Hash + Clone + fmt::Debug + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned + 'static,
+    P: AsRef<Path>,
+{
+    let (w, r) = crate::new();
+    LoggedWriteHandle::replay(w, 0, path.as_ref(), registry).map(|w| (w, r))
+}
+
+/// Restores the map from a snapshot written by [`LoggedWriteHandle::checkpoint`] and replays
+/// operations of the log at `path`, that are not part of the snapshot yet.
+pub fn open_with_snapshot<K, V, R, P>(
+    snapshot: R,
+    path: P,
+    registry: Registry<V>,
+) -> Result<LoggedHandles<K, V>, WalError>
+where
+    K: Eq + Hash + Clone + fmt::Debug + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned + 'static,
+    R: Read,
+    P: AsRef<Path>,
+{
+    let (seq, content) = snapshot::read(snapshot)?;
+    let (w, r) = crate::from_content(content);
+    LoggedWriteHandle::replay(w, seq, path.as_ref(), registry).map(|w| (w, r))
+}
+
+impl<K, V> LoggedWriteHandle<K, V>
+where
+    K: Eq + Hash + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned + 'static,
+{
+    fn replay(
+        mut handle: WriteHandle<K, V>,
+        since: u64,
+        path: &Path,
+        registry: Registry<V>,
+    ) -> Result<Self, WalError> {
+        let mut log = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let mut content = Vec::new();
+        log.read_to_end(&mut content)?;
+
+        let mut seq = since;
+        if content.is_empty() {
+            write_header(&mut log)?;
+        } else {
+            let mut reader = content.as_slice();
+            read_header(&mut reader)?;
+            while let Some(entry) = read_entry::<K, V>(&mut reader)? {
+                // skip operations, that are already part of the snapshot
+                if entry.seq >= since {
+                    handle.add_op(entry.record.into_operation(&registry)?);
+                    seq = entry.seq + 1;
+                }
+            }
+            // drop entry cut off by a crash, so new entries do not follow a broken one
+            log.set_len((content.len() - reader.len()) as u64)?;
+        }
+        handle.publish();
+
+        Ok(Self {
+            handle,
+            log,
+            registry,
+            seq,
+        })
+    }
+
+    fn log(&mut self, record: Record<K, V>) -> Result<&mut Self, WalError> {
+        let entry = Entry {
+            seq: self.seq,
+            record,
+        };
+        let mut buf = Vec::new();
+        ciborium::into_writer(&entry, &mut buf).map_err(|err| WalError::Encode(err.to_string()))?;
+        let op = entry.record.into_operation(&self.registry)?;
+
+        self.log.write_all(&buf)?;
+        self.seq += 1;
+        self.handle.add_op(op);
+        Ok(self)
+    }
+
+    /// Syncs the log to disk and publishes all pending operations.
+    pub fn publish(&mut self) -> Result<&mut Self, WalError> {
+        self.log.sync_data()?;
+        self.handle.publish();
+        Ok(self)
+    }
+
+    pub fn has_pending(&self) -> bool {
+        self.handle.has_pending()
+    }
+
+    /// Publishes pending operations, writes snapshot of the map into `writer` and truncates
+    /// the log. Snapshot remembers position in the log, so if the process dies before
+    /// the log is truncated, already snapshotted operations are skipped on replay.
+    pub fn checkpoint<W: Write>(&mut self, writer: W) -> Result<&mut Self, WalError> {
+        self.publish()?;
+        self.handle.write_snapshot(self.seq, writer)?;
+        self.log.set_len(0)?;
+        write_header(&mut self.log)?;
+        self.log.sync_data()?;
+        Ok(self)
+    }
+
+    pub fn insert(&mut self, k: K, v: V) -> Result<&mut Self, WalError> {
+        self.log(Record::Add(k, v))
+    }
+
+    /// Same as `WriteHandle::insert_with_ttl`. The deadline is logged as wall-clock time,
+    /// so it keeps running while the process is down.
+    pub fn insert_with_ttl(&mut self, k: K, v: V, ttl: Duration) -> Result<&mut Self, WalError> {
+        self.log(Record::AddWithTtl(k, v, unix_millis(Instant::now() + ttl)))
+    }
+
+    pub fn insert_if_absent(&mut self, k: K, v: V) -> Result<&mut Self, WalError> {
+        self.log(Record::AddIfAbsent(k, v))
+    }
+
+    pub fn update(&mut self, k: K, v: V) -> Result<&mut Self, WalError> {
+        self.log(Record::Replace(k, v, unix_millis(Instant::now())))
+    }
+
+    pub fn update_or_insert(&mut self, k: K, v: V) -> Result<&mut Self, WalError> {
+        self.log(Record::ReplaceOrAdd(k, v, unix_millis(Instant::now())))
+    }
+
+    pub fn extend<I>(&mut self, entries: I) -> Result<&mut Self, WalError>
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.log(Record::Extend(entries.into_iter().collect()))
+    }
+
+    pub fn replace_all(&mut self, entries: HashMap<K, V>) -> Result<&mut Self, WalError> {
+        self.log(Record::ReplaceAll(entries))
+    }
+
+    /// Same as `WriteHandle::sweep`, replay removes entries expired at the time of this call.
+    pub fn sweep(&mut self) -> Result<&mut Self, WalError> {
+        self.log(Record::Sweep(unix_millis(Instant::now())))
+    }
+
+    pub fn remove(&mut self, k: K) -> Result<&mut Self, WalError> {
+        self.log(Record::Remove(k))
+    }
+
+    pub fn purge(&mut self) -> Result<&mut Self, WalError> {
+        self.log(Record::Purge)
+    }
+
+    /// Same as `WriteHandle::modify`, with modifier registered under `name`.
+    pub fn modify_named(&mut self, k: K, name: &str) -> Result<&mut Self, WalError> {
+        self.registry.modifier(name)?;
+        self.log(Record::Modify(
+            k,
+            name.to_string(),
+            unix_millis(Instant::now()),
+        ))
+    }
+
+    /// Same as `WriteHandle::upsert`, with modifier registered under `name`.
+    pub fn upsert_named(&mut self, k: K, default: V, name: &str) -> Result<&mut Self, WalError> {
+        self.registry.modifier(name)?;
+        self.log(Record::Upsert(
+            k,
+            default,
+            name.to_string(),
+            unix_millis(Instant::now()),
+        ))
+    }
+
+    /// Same as `WriteHandle::remove_if`, with predicate registered under `name`.
+    pub fn remove_if_named(&mut self, k: K, name: &str) -> Result<&mut Self, WalError> {
+        self.registry.predicate(name)?;
+        self.log(Record::RemoveIf(k, name.to_string()))
+    }
+
+    /// Same as `WriteHandle::retain`, keeping entries, whose value matches the predicate
+    /// registered under `name`.
+    pub fn retain_named(&mut self, name: &str) -> Result<&mut Self, WalError> {
+        self.registry.predicate(name)?;
+        self.log(Record::Retain(name.to_string()))
+    }
+}
+
+fn write_header(log: &mut File) -> io::Result<()> {
+    log.write_all(&MAGIC)?;
+    log.write_all(&LOG_VERSION.to_le_bytes())
+}
+
+fn read_header<R: Read>(reader: &mut R) -> Result<(), WalError> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(WalError::InvalidHeader);
+    }
+
+    let mut version = [0u8; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != LOG_VERSION {
+        return Err(WalError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+/// Reads next entry of the log. Entry cut off by a crash in the middle of a write ends the log
+/// and is left unread in `reader`.
+fn read_entry<K, V>(reader: &mut &[u8]) -> Result<Option<Entry<K, V>>, WalError>
+where
+    K: Eq + Hash + DeserializeOwned,
+    V: DeserializeOwned,
+{
+    if reader.is_empty() {
+        return Ok(None);
+    }
+    let mut attempt = *reader;
+    match ciborium::from_reader(&mut attempt) {
+        Ok(entry) => {
+            *reader = attempt;
+            Ok(Some(entry))
+        }
+        Err(ciborium::de::Error::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(err) => Err(WalError::Decode(err.to_string())),
+    }
+}
+
+#[derive(Debug)]
+pub enum WalError {
+    Io(io::Error),
+    Snapshot(SnapshotError),
+    /// Log file does not start with the log header at all.
+    InvalidHeader,
+    /// Log was written by an incompatible version, which is carried by the variant.
+    UnsupportedVersion(u32),
+    Encode(String),
+    Decode(String),
+    /// Modifier with this name is not registered in the [`Registry`].
+    UnknownModifier(String),
+    /// Predicate with this name is not registered in the [`Registry`].
+    UnknownPredicate(String),
+}
+
+impl fmt::Display for WalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalError::Io(err) => write!(f, "log io error: {}", err),
+            WalError::Snapshot(err) => write!(f, "{}", err),
+            WalError::InvalidHeader => write!(f, "file is not a left-right-map log"),
+            WalError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported log version {}, expected {}",
+                version, LOG_VERSION
+            ),
+            WalError::Encode(err) => write!(f, "failed to encode log entry: {}", err),
+            WalError::Decode(err) => write!(f, "failed to decode log entry: {}", err),
+            WalError::UnknownModifier(name) => write!(f, "modifier '{}' is not registered", name),
+            WalError::UnknownPredicate(name) => {
+                write!(f, "predicate '{}' is not registered", name)
+            }
+        }
+    }
+}
+
+impl Error for WalError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            WalError::Io(err) => Some(err),
+            WalError::Snapshot(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for WalError {
+    fn from(err: io::Error) -> Self {
+        WalError::Io(err)
+    }
+}
+
+impl From<SnapshotError> for WalError {
+    fn from(err: SnapshotError) -> Self {
+        WalError::Snapshot(err)
+    }
+}
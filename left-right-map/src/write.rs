@@ -1,38 +1,66 @@
-use crate::inner::Inner;
+use crate::epoch::{Notifier, Publisher};
+use crate::inner::{Inner, Store};
 use crate::read::ReadHandle;
+use crate::sync::{Mutex, MutexGuard};
+use crate::transaction::{Transaction, TransactionError};
+use crate::view::{Overlay, WriterView};
 use left_right::Absorb;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fmt;
-use std::hash::Hash;
-use std::sync::{Arc, Mutex, MutexGuard};
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Operation of a hash map copy, which is replaced by a `HashMap` with the same hasher.
+pub(crate) type MapOperation<K, V, S> = Operation<K, V, HashMap<K, V, S>>;
+
+type InnerWriteHandle<K, V, S> = left_right::WriteHandle<Inner<K, V, S>, MapOperation<K, V, S>>;
 
 /// Takes read_handle out of mutex, so we can read through WriteHandle without locking
 #[derive(Clone)]
-pub struct SharedWriteHandle<K, V>
+pub struct SharedWriteHandle<K, V, S = RandomState>
 where
     K: Eq + Hash + Clone,
     V: Clone,
+    S: BuildHasher + Clone,
 {
-    handle: Arc<Mutex<left_right::WriteHandle<Inner<K, V>, Operation<K, V>>>>,
-    read_handle: ReadHandle<K, V>,
+    handle: Arc<Mutex<InnerWriteHandle<K, V, S>>>,
+    read_handle: ReadHandle<K, V, S>,
+    publisher: Arc<Publisher>,
+    /// Operations appended by all clones since the last publish, only changed under the lock.
+    pending: Arc<AtomicUsize>,
+    max_pending: Option<usize>,
 }
 
-impl<K, V> From<WriteHandle<K, V>> for SharedWriteHandle<K, V>
+impl<K, V, S> From<WriteHandle<K, V, S>> for SharedWriteHandle<K, V, S>
 where
     K: Eq + Hash + Clone,
     V: Clone,
+    S: BuildHasher + Clone,
 {
-    fn from(value: WriteHandle<K, V>) -> Self {
+    fn from(mut value: WriteHandle<K, V, S>) -> Self {
+        // shared handles do not report missed updates
+        value.set_strict_updates(false);
         Self {
             handle: Arc::new(Mutex::new(value.handle)),
             read_handle: value.read_handle,
+            publisher: Arc::new(value.publisher),
+            pending: Arc::new(AtomicUsize::new(value.pending)),
+            max_pending: value.max_pending,
         }
     }
 }
 
-impl<K, V> fmt::Debug for SharedWriteHandle<K, V>
+impl<K, V, S> fmt::Debug for SharedWriteHandle<K, V, S>
 where
     K: Eq + Hash + Clone + fmt::Debug,
     V: fmt::Debug + Clone,
+    S: BuildHasher + Clone + fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("SharedWriteHandle")
@@ -42,67 +70,248 @@ where
     }
 }
 
-impl<K, V> SharedWriteHandle<K, V>
+impl<K, V, S> SharedWriteHandle<K, V, S>
 where
     K: Eq + Hash + Clone,
     V: Clone,
+    S: BuildHasher + Clone,
 {
-    pub fn inner(
-        &mut self,
-    ) -> MutexGuard<'_, left_right::WriteHandle<Inner<K, V>, Operation<K, V>>> {
+    // public since the first release, even though its types are not nameable outside the crate
+    #[allow(private_interfaces)]
+    pub fn inner(&self) -> MutexGuard<'_, InnerWriteHandle<K, V, S>> {
         self.handle.lock().unwrap()
     }
 
     pub fn publish(&mut self) {
-        self.handle.lock().unwrap().publish();
+        self.publish_locked(&mut self.inner());
+    }
+
+    fn publish_locked(&self, handle: &mut InnerWriteHandle<K, V, S>) {
+        publish_shared(handle, &self.publisher, &self.pending);
+    }
+
+    /// Counts `appended` operations and publishes, if there are too many of them pending.
+    fn appended(&self, handle: &mut InnerWriteHandle<K, V, S>, appended: usize) {
+        let pending = self.pending.fetch_add(appended, Ordering::Relaxed) + appended;
+        if self.max_pending.is_some_and(|max| pending >= max) {
+            self.publish_locked(handle);
+        }
     }
 
-    pub(crate) fn add_op(&mut self, op: Operation<K, V>) -> &mut Self {
-        self.handle.lock().unwrap().append(op);
+    pub(crate) fn add_op(&mut self, op: MapOperation<K, V, S>) -> &mut Self {
+        let mut handle = self.inner();
+        handle.append(op);
+        self.appended(&mut handle, 1);
+        drop(handle);
         self
     }
 
+    /// Publishes automatically once `max_pending` operations are waiting, counting operations
+    /// appended by all clones of this handle. `None` turns it off.
+    /// Applies only to this handle and clones created from it afterwards.
+    pub fn set_max_pending(&mut self, max_pending: Option<usize>) -> &mut Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    /// Returns a guard, that publishes once it is dropped.
+    pub fn batch(&mut self) -> WriteBatch<'_, Self> {
+        WriteBatch::new(self, Self::publish)
+    }
+
     pub fn has_pending(&self) -> bool {
-        self.handle.lock().unwrap().has_pending_operations()
+        self.inner().has_pending_operations()
     }
 
     pub fn insert(&mut self, k: K, v: V) {
         self.add_op(Operation::Add(k, v));
     }
 
+    /// Inserts the value, which readers stop seeing once `ttl` elapses.
+    pub fn insert_with_ttl(&mut self, k: K, v: V, ttl: Duration) {
+        self.add_op(Operation::AddWithTtl(k, v, Instant::now() + ttl));
+    }
+
+    /// Removes entries, that are expired by now, once published.
+    pub fn sweep(&mut self) {
+        self.add_op(Operation::Sweep(Instant::now()));
+    }
+
+    pub fn touch(&mut self, k: K) {
+        self.add_op(Operation::Touch(k));
+    }
+
     pub fn remove(&mut self, k: K) {
         self.add_op(Operation::Remove(k));
     }
 
     pub fn update(&mut self, k: K, v: V) {
-        self.add_op(Operation::Replace(k, v));
+        self.add_op(Operation::Replace(k, v, Instant::now()));
+    }
+
+    /// Replaces the value of an existing key, keeping its deadline, or inserts it.
+    pub fn update_or_insert(&mut self, k: K, v: V) {
+        self.add_op(Operation::ReplaceOrAdd(k, v, Instant::now()));
     }
 
     pub fn purge(&mut self) {
         self.add_op(Operation::Purge);
     }
 
+    pub fn retain<F>(&mut self, predicate: F)
+    where
+        F: Fn(&K, &V) -> bool + Send + 'static,
+    {
+        self.add_op(Operation::Retain(Filter(Box::new(predicate))));
+    }
+
+    pub fn extend<I>(&mut self, entries: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.add_op(Operation::Extend(entries.into_iter().collect()));
+    }
+
+    pub fn replace_all(&mut self, entries: HashMap<K, V, S>) {
+        self.add_op(Operation::ReplaceAll(entries));
+    }
+
     pub fn modify<F>(&mut self, k: K, modifier: F) -> &mut Self
     where
-        F: Fn(&mut V) -> () + Send + 'static,
+        F: Fn(&mut V) + Send + 'static,
+    {
+        self.add_op(Operation::Modify(
+            k,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ))
+    }
+
+    pub fn insert_if_absent(&mut self, k: K, v: V) {
+        self.add_op(Operation::AddIfAbsent(k, v));
+    }
+
+    pub fn upsert<F>(&mut self, k: K, default: V, modifier: F) -> &mut Self
+    where
+        F: Fn(&mut V) + Send + 'static,
     {
-        self.add_op(Operation::Modify(k, Modifier(Box::new(modifier))))
+        self.add_op(Operation::Upsert(
+            k,
+            default,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ))
+    }
+
+    pub fn remove_if<F>(&mut self, k: K, predicate: F) -> &mut Self
+    where
+        F: Fn(&V) -> bool + Send + 'static,
+    {
+        self.add_op(Operation::RemoveIf(k, Predicate(Box::new(predicate))))
+    }
+
+    /// Validates preconditions of the transaction and appends all of its operations,
+    /// while holding the lock, so no other writer can interleave with them.
+    /// Operations appended before are published first, so preconditions see them.
+    pub fn commit(&mut self, transaction: Transaction<K, V>) -> Result<(), TransactionError<K>> {
+        let mut handle = self.inner();
+        if handle.has_pending_operations() || !self.publisher.has_published() {
+            self.publish_locked(&mut handle);
+        }
+        let now = Instant::now();
+        transaction.validate(&*handle.enter().expect("writer is alive"), now)?;
+        let operations = transaction.into_operations(now);
+        let appended = operations.len();
+        handle.extend(operations);
+        self.appended(&mut handle, appended);
+        Ok(())
+    }
+
+    /// Writes currently published content of the map into `writer`.
+    /// Readers are not blocked, but publishing is stalled until the snapshot is written.
+    #[cfg(feature = "serde")]
+    pub fn snapshot_to<W: std::io::Write>(
+        &self,
+        writer: W,
+    ) -> Result<(), crate::snapshot::SnapshotError>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        let guard = self.read_handle.handle.enter().expect("writer is alive");
+        crate::snapshot::write(&guard, 0, writer)
     }
 }
 
-pub struct WriteHandle<K, V>
+impl<K, V, S> SharedWriteHandle<K, V, S>
 where
+    K: Eq + Hash + Clone + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+    S: BuildHasher + Clone + Send + Sync + 'static,
+{
+    /// Spawns a thread, that publishes pending operations every `max_staleness`, so readers
+    /// never see data older than that. The thread exits within `max_staleness` after all
+    /// clones of the handle are dropped.
+    pub fn publish_every(&self, max_staleness: Duration) -> thread::JoinHandle<()> {
+        let handle = Arc::downgrade(&self.handle);
+        let publisher = Arc::downgrade(&self.publisher);
+        let pending = self.pending.clone();
+        thread::spawn(move || loop {
+            thread::sleep(max_staleness);
+            let (Some(handle), Some(publisher)) = (handle.upgrade(), publisher.upgrade()) else {
+                return;
+            };
+            let mut handle = handle.lock().unwrap();
+            if pending.load(Ordering::Relaxed) > 0 {
+                publish_shared(&mut handle, &publisher, &pending);
+            }
+        })
+    }
+}
+
+fn publish_shared<K, V, S>(
+    handle: &mut InnerWriteHandle<K, V, S>,
+    publisher: &Publisher,
+    pending: &AtomicUsize,
+) where
     K: Eq + Hash + Clone,
     V: Clone,
+    S: BuildHasher + Clone,
 {
-    handle: left_right::WriteHandle<Inner<K, V>, Operation<K, V>>,
-    read_handle: ReadHandle<K, V>,
+    #[cfg(feature = "metrics")]
+    let started = Instant::now();
+    handle.publish();
+    publisher.published();
+    let _operations = pending.swap(0, Ordering::Relaxed);
+    #[cfg(feature = "metrics")]
+    publisher.record(started, _operations, || {
+        handle.enter().map_or(0, |inner| inner.data.len())
+    });
 }
 
-impl<K, V> fmt::Debug for WriteHandle<K, V>
+pub struct WriteHandle<K, V, S = RandomState>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    handle: InnerWriteHandle<K, V, S>,
+    read_handle: ReadHandle<K, V, S>,
+    publisher: Publisher,
+    pending: usize,
+    max_pending: Option<usize>,
+    strict: bool,
+    /// Whether a report of missed updates was started since the last publish.
+    reporting: bool,
+    /// Effects of pending operations, tracked only once the view is enabled.
+    overlay: Option<Overlay<K, V, S>>,
+}
+
+impl<K, V, S> fmt::Debug for WriteHandle<K, V, S>
 where
     K: Eq + Hash + Clone + fmt::Debug,
     V: fmt::Debug + Clone,
+    S: BuildHasher + Clone + fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("WriteHandle")
@@ -112,21 +321,99 @@ where
     }
 }
 
-impl<K, V> WriteHandle<K, V>
+impl<K, V, S> WriteHandle<K, V, S>
 where
     K: Eq + Hash + Clone,
     V: Clone,
+    S: BuildHasher + Clone,
 {
-    pub(crate) fn new(handle: left_right::WriteHandle<Inner<K, V>, Operation<K, V>>) -> Self {
-        let read_handle = ReadHandle::new(left_right::ReadHandle::clone(&*handle));
+    pub(crate) fn new(handle: InnerWriteHandle<K, V, S>, notifier: Arc<Notifier>) -> Self {
+        let read_handle =
+            ReadHandle::new(left_right::ReadHandle::clone(&*handle), notifier.clone());
         Self {
             handle,
             read_handle,
+            publisher: Publisher::new(notifier),
+            pending: 0,
+            max_pending: None,
+            strict: false,
+            reporting: false,
+            overlay: None,
         }
     }
 
-    pub fn publish(&mut self) -> &mut Self {
+    /// Exposes pending operations to readers. In strict update mode, the report lists
+    /// updates of the published batch, that did nothing, otherwise it is always empty.
+    pub fn publish(&mut self) -> PublishReport<K> {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
         self.handle.publish();
+        self.publisher.published();
+        if let Some(overlay) = &mut self.overlay {
+            overlay.clear();
+        }
+        #[cfg(feature = "metrics")]
+        self.publisher.record(started, self.pending, || {
+            self.handle.enter().map_or(0, |inner| inner.data.len())
+        });
+        self.pending = 0;
+        if !std::mem::take(&mut self.reporting) {
+            return PublishReport::default();
+        }
+        let inner = self.handle.enter().expect("writer is alive");
+        PublishReport {
+            missing: inner.missed.clone().unwrap_or_default(),
+        }
+    }
+
+    /// In strict update mode, `update` and `modify` of a key missing at the time the operation
+    /// is applied are reported by the next `publish`. Reports of publishes triggered by
+    /// `max_pending`, a dropped batch or a commit are discarded.
+    /// Turning the mode off discards the report of the pending batch.
+    pub fn set_strict_updates(&mut self, strict: bool) -> &mut Self {
+        if self.strict && !strict {
+            self.handle.append(Operation::Report(false));
+            self.reporting = false;
+        }
+        self.strict = strict;
+        self
+    }
+
+    /// Starts the report of the current batch before its first operation, so every copy
+    /// of the map records misses of exactly the operations of the batch.
+    fn start_report(&mut self) {
+        if self.strict && !self.reporting {
+            self.handle.append(Operation::Report(true));
+            self.reporting = true;
+        }
+    }
+
+    /// Reports every publish of this writer, and of shared writers created from it,
+    /// to `recorder`.
+    #[cfg(feature = "metrics")]
+    pub fn set_recorder(&mut self, recorder: Arc<dyn crate::metrics::Recorder>) -> &mut Self {
+        self.publisher.recorder = Some(recorder);
+        self
+    }
+
+    /// Publishes automatically once `max_pending` operations are waiting. `None` turns it off.
+    pub fn set_max_pending(&mut self, max_pending: Option<usize>) -> &mut Self {
+        self.max_pending = max_pending;
+        self
+    }
+
+    /// Returns a guard, that publishes once it is dropped.
+    pub fn batch(&mut self) -> WriteBatch<'_, Self> {
+        WriteBatch::new(self, |w| {
+            w.publish();
+        })
+    }
+
+    fn appended(&mut self, appended: usize) -> &mut Self {
+        self.pending += appended;
+        if self.max_pending.is_some_and(|max| self.pending >= max) {
+            self.publish();
+        }
         self
     }
 
@@ -134,130 +421,590 @@ where
         self.handle.has_pending_operations()
     }
 
-    pub(crate) fn add_op(&mut self, op: Operation<K, V>) -> &mut Self {
-        self.handle.append(op);
+    /// Publishes operations readers do not see yet, including those applied before
+    /// the first publish, which are not pending.
+    fn publish_unseen(&mut self) {
+        if self.has_pending() || !self.publisher.has_published() {
+            self.publish();
+        }
+    }
+
+    #[cfg(feature = "tokio")]
+    pub(crate) fn read_handle(&self) -> &ReadHandle<K, V, S> {
+        &self.read_handle
+    }
+
+    /// Starts tracking effects of appended operations, so [`WriteHandle::view`] and
+    /// [`WriteHandle::commit`] see them before they are published. Tracking applies every
+    /// operation once more on the writer side, so it is off until enabled.
+    /// Operations appended before were not tracked, so they are published first.
+    pub fn enable_view(&mut self) -> &mut Self {
+        if self.overlay.is_some() {
+            return self;
+        }
+        self.publish_unseen();
+        let published = self.handle.enter().expect("writer is alive");
+        let overlay = Overlay::with_hasher(published.data.hasher().clone());
+        drop(published);
+        self.overlay = Some(overlay);
         self
     }
 
+    /// Returns content of the map as this writer sees it, including operations,
+    /// that are not published yet. `None` unless enabled by [`WriteHandle::enable_view`].
+    pub fn view(&self) -> Option<WriterView<'_, K, V, S>> {
+        let overlay = self.overlay.as_ref()?;
+        let published = self.handle.enter().expect("writer is alive");
+        Some(WriterView::new(published, overlay))
+    }
+
+    pub(crate) fn add_op(&mut self, mut op: MapOperation<K, V, S>) -> &mut Self {
+        self.start_report();
+        if let Some(overlay) = &mut self.overlay {
+            let published = self.handle.enter().expect("writer is alive");
+            overlay.apply(&mut op, &published);
+        }
+        self.handle.append(op);
+        self.appended(1)
+    }
+
     pub fn insert(&mut self, k: K, v: V) -> &mut Self {
         self.add_op(Operation::Add(k, v))
     }
 
+    /// Inserts the value, which readers stop seeing once `ttl` elapses.
+    /// Until swept, expired entry still occupies memory and is visible to writer operations,
+    /// like `insert_if_absent`. Updates, upserts and transaction preconditions treat it
+    /// as missing.
+    /// Deadline is dropped by plain `insert` of the same key. Snapshots keep it as wall-clock time.
+    pub fn insert_with_ttl(&mut self, k: K, v: V, ttl: Duration) -> &mut Self {
+        self.add_op(Operation::AddWithTtl(k, v, Instant::now() + ttl))
+    }
+
+    /// Physically removes entries, that are expired at the time of this call, on next publish.
+    pub fn sweep(&mut self) -> &mut Self {
+        self.add_op(Operation::Sweep(Instant::now()))
+    }
+
+    /// Records a use of the key by readers, so bounded map evicts it later.
+    /// Does nothing for maps without capacity.
+    pub fn touch(&mut self, k: K) -> &mut Self {
+        self.add_op(Operation::Touch(k))
+    }
+
     pub fn remove(&mut self, k: K) -> &mut Self {
         self.add_op(Operation::Remove(k))
     }
 
+    /// Replaces the value of an existing key, does nothing if the key is missing or expired.
+    /// See [`WriteHandle::set_strict_updates`] to learn about such updates.
     pub fn update(&mut self, k: K, v: V) -> &mut Self {
-        self.add_op(Operation::Replace(k, v))
+        self.add_op(Operation::Replace(k, v, Instant::now()))
+    }
+
+    /// Replaces the value of an existing key, keeping its deadline, or inserts it.
+    /// An expired key is inserted anew, without the deadline.
+    pub fn update_or_insert(&mut self, k: K, v: V) -> &mut Self {
+        self.add_op(Operation::ReplaceOrAdd(k, v, Instant::now()))
     }
 
     pub fn purge(&mut self) -> &mut Self {
         self.add_op(Operation::Purge)
     }
 
+    /// Keeps only entries, for which `predicate` returns true.
+    /// Predicate must be deterministic, because it is run once for each copy of the map.
+    pub fn retain<F>(&mut self, predicate: F) -> &mut Self
+    where
+        F: Fn(&K, &V) -> bool + Send + 'static,
+    {
+        self.add_op(Operation::Retain(Filter(Box::new(predicate))))
+    }
+
+    /// Inserts all entries as a single operation, so loading many entries does not grow
+    /// the oplog by one operation per entry.
+    pub fn extend<I>(&mut self, entries: I) -> &mut Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        self.add_op(Operation::Extend(entries.into_iter().collect()))
+    }
+
+    /// Replaces the whole content of the map with `entries`, e.g. a freshly loaded `HashMap`,
+    /// as a single operation. The map is cloned once for the first copy and moved into
+    /// the second one, its entries are not rehashed.
+    pub fn replace_all(&mut self, entries: HashMap<K, V, S>) -> &mut Self {
+        self.add_op(Operation::ReplaceAll(entries))
+    }
+
     pub fn modify<F>(&mut self, k: K, modifier: F) -> &mut Self
     where
-        F: Fn(&mut V) -> () + Send + 'static,
+        F: Fn(&mut V) + Send + 'static,
+    {
+        self.add_op(Operation::Modify(
+            k,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ))
+    }
+
+    /// Inserts the value only if the key is not present at the time the operation is applied.
+    pub fn insert_if_absent(&mut self, k: K, v: V) -> &mut Self {
+        self.add_op(Operation::AddIfAbsent(k, v))
+    }
+
+    /// Applies `modifier` to the existing value, or inserts `default` if the key is missing.
+    /// Modifier must be deterministic, because it is run once for each copy of the map.
+    pub fn upsert<F>(&mut self, k: K, default: V, modifier: F) -> &mut Self
+    where
+        F: Fn(&mut V) + Send + 'static,
+    {
+        self.add_op(Operation::Upsert(
+            k,
+            default,
+            Modifier(Box::new(modifier)),
+            Instant::now(),
+        ))
+    }
+
+    /// Removes the key only if `predicate` returns true for its current value.
+    /// Predicate must be deterministic, because it is run once for each copy of the map.
+    pub fn remove_if<F>(&mut self, k: K, predicate: F) -> &mut Self
+    where
+        F: Fn(&V) -> bool + Send + 'static,
+    {
+        self.add_op(Operation::RemoveIf(k, Predicate(Box::new(predicate))))
+    }
+
+    /// Validates preconditions of the transaction and appends all of its operations.
+    /// If any precondition fails, nothing is appended. Preconditions are checked against
+    /// the [`WriteHandle::view`] if it is enabled. Otherwise operations appended before
+    /// are published first, so preconditions see them.
+    pub fn commit(
+        &mut self,
+        transaction: Transaction<K, V>,
+    ) -> Result<&mut Self, TransactionError<K>> {
+        if self.overlay.is_none() {
+            self.publish_unseen();
+        }
+        let published = self.handle.enter().expect("writer is alive");
+        let operations = match &mut self.overlay {
+            Some(overlay) => overlay.commit(transaction, &published)?,
+            None => {
+                let now = Instant::now();
+                transaction.validate(&*published, now)?;
+                transaction.into_operations(now)
+            }
+        };
+        drop(published);
+        let appended = operations.len();
+        self.start_report();
+        self.handle.extend(operations);
+        Ok(self.appended(appended))
+    }
+
+    /// Writes currently published content of the map into `writer`.
+    /// Pending operations are not part of the snapshot, publish first to include them.
+    #[cfg(feature = "serde")]
+    pub fn snapshot_to<W: std::io::Write>(
+        &self,
+        writer: W,
+    ) -> Result<(), crate::snapshot::SnapshotError>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
     {
-        self.add_op(Operation::Modify(k, Modifier(Box::new(modifier))))
+        self.write_snapshot(0, writer)
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn write_snapshot<W: std::io::Write>(
+        &self,
+        seq: u64,
+        writer: W,
+    ) -> Result<(), crate::snapshot::SnapshotError>
+    where
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        let guard = self.handle.enter().expect("writer is alive");
+        crate::snapshot::write(&guard, seq, writer)
+    }
+}
+
+/// Updates of a published batch, that did nothing. Filled only in strict update mode,
+/// see [`WriteHandle::set_strict_updates`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublishReport<K> {
+    missing: Vec<K>,
+}
+
+impl<K> Default for PublishReport<K> {
+    fn default() -> Self {
+        Self {
+            missing: Vec::new(),
+        }
+    }
+}
+
+impl<K> PublishReport<K> {
+    /// Keys of `update` and `modify` operations, that found no entry, in order of the operations.
+    pub fn missing(&self) -> &[K] {
+        &self.missing
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    pub fn into_missing(self) -> Vec<K> {
+        self.missing
+    }
+}
+
+/// Guard for a group of writes, that are published together once the guard is dropped.
+/// Dereferences to the writer it was created from.
+pub struct WriteBatch<'w, W> {
+    handle: &'w mut W,
+    publish: fn(&mut W),
+}
+
+impl<'w, W> WriteBatch<'w, W> {
+    fn new(handle: &'w mut W, publish: fn(&mut W)) -> Self {
+        Self { handle, publish }
+    }
+}
+
+impl<W: fmt::Debug> fmt::Debug for WriteBatch<'_, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteBatch")
+            .field("handle", &self.handle)
+            .finish()
     }
 }
 
-pub(crate) enum Operation<K, V> {
+impl<W> Deref for WriteBatch<'_, W> {
+    type Target = W;
+
+    fn deref(&self) -> &W {
+        self.handle
+    }
+}
+
+impl<W> DerefMut for WriteBatch<'_, W> {
+    fn deref_mut(&mut self) -> &mut W {
+        self.handle
+    }
+}
+
+impl<W> Drop for WriteBatch<'_, W> {
+    fn drop(&mut self) {
+        (self.publish)(self.handle);
+    }
+}
+
+/// Operation of the writer, `D` is the whole content of a map copy, see [`Store::Data`].
+pub(crate) enum Operation<K, V, D> {
     Add(K, V),
-    Replace(K, V),
+    AddWithTtl(K, V, Instant),
+    AddIfAbsent(K, V),
+    /// Updates take the time they were appended at, entries expired by then count as missing.
+    Replace(K, V, Instant),
+    ReplaceOrAdd(K, V, Instant),
+    Upsert(K, V, Modifier<V>, Instant),
     Remove(K),
-    Modify(K, Modifier<V>),
+    RemoveIf(K, Predicate<V>),
+    Move(K, K),
+    Modify(K, Modifier<V>, Instant),
+    Touch(K),
+    Retain(Filter<K, V>),
+    Extend(Vec<(K, V)>),
+    ReplaceAll(D),
     Purge,
+    Sweep(Instant),
+    /// Starts a report of missed updates, or stops reporting them.
+    Report(bool),
     MarkReady,
 }
 
-impl<K, V> fmt::Debug for Operation<K, V>
+impl<K, V, D> fmt::Debug for Operation<K, V, D>
 where
     K: fmt::Debug,
     V: fmt::Debug,
+    D: fmt::Debug,
 {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
-            Operation::Replace(ref a, ref b) => f.debug_tuple("Replace").field(a).field(b).finish(),
+            Operation::Replace(ref a, ref b, ref c) => {
+                f.debug_tuple("Replace").field(a).field(b).field(c).finish()
+            }
+            Operation::ReplaceOrAdd(ref a, ref b, ref c) => f
+                .debug_tuple("ReplaceOrAdd")
+                .field(a)
+                .field(b)
+                .field(c)
+                .finish(),
             Operation::Add(ref a, ref b) => f.debug_tuple("Add").field(a).field(b).finish(),
+            Operation::AddWithTtl(ref a, ref b, ref c) => f
+                .debug_tuple("AddWithTtl")
+                .field(a)
+                .field(b)
+                .field(c)
+                .finish(),
+            Operation::AddIfAbsent(ref a, ref b) => {
+                f.debug_tuple("AddIfAbsent").field(a).field(b).finish()
+            }
+            Operation::Upsert(ref a, ref b, ref c, ref d) => f
+                .debug_tuple("Upsert")
+                .field(a)
+                .field(b)
+                .field(c)
+                .field(d)
+                .finish(),
             Operation::Remove(ref a) => f.debug_tuple("Remove").field(a).finish(),
-            Operation::Modify(ref a, ref b) => f.debug_tuple("Modify").field(a).field(b).finish(),
+            Operation::RemoveIf(ref a, ref b) => {
+                f.debug_tuple("RemoveIf").field(a).field(b).finish()
+            }
+            Operation::Move(ref a, ref b) => f.debug_tuple("Move").field(a).field(b).finish(),
+            Operation::Modify(ref a, ref b, ref c) => {
+                f.debug_tuple("Modify").field(a).field(b).field(c).finish()
+            }
+            Operation::Touch(ref a) => f.debug_tuple("Touch").field(a).finish(),
+            Operation::Retain(ref a) => f.debug_tuple("Retain").field(a).finish(),
+            Operation::Extend(ref a) => f.debug_tuple("Extend").field(a).finish(),
+            Operation::ReplaceAll(ref a) => f.debug_tuple("ReplaceAll").field(a).finish(),
             Operation::Purge => f.debug_tuple("Purge").finish(),
+            Operation::Sweep(ref a) => f.debug_tuple("Sweep").field(a).finish(),
+            Operation::Report(ref a) => f.debug_tuple("Report").field(a).finish(),
             Operation::MarkReady => f.debug_tuple("MarkReady").finish(),
         }
     }
 }
 
-impl<K, V> Absorb<Operation<K, V>> for Inner<K, V>
+impl<K, V> Operation<K, V, Infallible> {
+    /// Operations, that do not replace the whole content, fit a map of any kind.
+    pub(crate) fn cast<D>(self) -> Operation<K, V, D> {
+        match self {
+            Operation::Add(k, v) => Operation::Add(k, v),
+            Operation::AddWithTtl(k, v, at) => Operation::AddWithTtl(k, v, at),
+            Operation::AddIfAbsent(k, v) => Operation::AddIfAbsent(k, v),
+            Operation::Replace(k, v, now) => Operation::Replace(k, v, now),
+            Operation::ReplaceOrAdd(k, v, now) => Operation::ReplaceOrAdd(k, v, now),
+            Operation::Upsert(k, v, modifier, now) => Operation::Upsert(k, v, modifier, now),
+            Operation::Remove(k) => Operation::Remove(k),
+            Operation::RemoveIf(k, predicate) => Operation::RemoveIf(k, predicate),
+            Operation::Move(from, to) => Operation::Move(from, to),
+            Operation::Modify(k, modifier, now) => Operation::Modify(k, modifier, now),
+            Operation::Touch(k) => Operation::Touch(k),
+            Operation::Retain(filter) => Operation::Retain(filter),
+            Operation::Extend(entries) => Operation::Extend(entries),
+            Operation::ReplaceAll(never) => match never {},
+            Operation::Purge => Operation::Purge,
+            Operation::Sweep(now) => Operation::Sweep(now),
+            Operation::Report(enabled) => Operation::Report(enabled),
+            Operation::MarkReady => Operation::MarkReady,
+        }
+    }
+}
+
+impl<K, V, D> Operation<K, V, D>
 where
-    K: Eq + Hash + Clone,
+    K: Clone,
     V: Clone,
+    D: Clone,
 {
-    fn absorb_first(&mut self, operation: &mut Operation<K, V>, _other: &Self) {
-        match operation {
+    /// Applies the operation to the first copy, operation is kept for the second one.
+    pub(crate) fn apply_first<M: Store<K, V, Data = D>>(&mut self, store: &mut M) {
+        match self {
             Operation::Add(ref key, ref value) => {
-                self.data.insert(key.clone(), value.clone());
+                store.insert(key.clone(), value.clone());
+            }
+            Operation::AddWithTtl(ref key, ref value, at) => {
+                store.insert(key.clone(), value.clone());
+                store.set_expiry(key.clone(), *at);
+            }
+            Operation::AddIfAbsent(ref key, ref value) => {
+                if store.get(key).is_none() {
+                    store.insert(key.clone(), value.clone());
+                }
             }
-            Operation::Replace(ref key, ref value) => {
-                if let Some(v) = self.data.get_mut(key) {
-                    *v = value.clone();
+            Operation::Replace(ref key, ref value, now) => match store.get_live_mut(key, *now) {
+                Some(v) => *v = value.clone(),
+                None => store.missed(key),
+            },
+            Operation::ReplaceOrAdd(ref key, ref value, now) => {
+                match store.get_live_mut(key, *now) {
+                    Some(v) => *v = value.clone(),
+                    // insert drops the deadline of an expired entry
+                    None => store.insert(key.clone(), value.clone()),
+                }
+            }
+            Operation::Upsert(ref key, ref default, ref modifier, now) => {
+                match store.get_live_mut(key, *now) {
+                    Some(v) => modifier.modify(v),
+                    None => store.insert(key.clone(), default.clone()),
                 }
             }
             Operation::Remove(ref key) => {
-                self.data.remove(key);
+                store.remove(key);
+            }
+            Operation::RemoveIf(ref key, ref predicate) => {
+                if store.get(key).is_some_and(|v| predicate.test(v)) {
+                    store.remove(key);
+                }
             }
-            Operation::Modify(ref key, ref modifier) => {
-                if let Some(v) = self.data.get_mut(key) {
-                    modifier.modify(v);
+            Operation::Move(ref from, ref to) => {
+                let expiry = store.expiry(from);
+                if let Some(v) = store.remove(from) {
+                    store.insert(to.clone(), v);
+                    if let Some(at) = expiry {
+                        store.set_expiry(to.clone(), at);
+                    }
                 }
             }
+            Operation::Modify(ref key, ref modifier, now) => match store.get_live_mut(key, *now) {
+                Some(v) => modifier.modify(v),
+                None => store.missed(key),
+            },
+            Operation::Touch(ref key) => {
+                // counts as a use of the key in bounded maps
+                store.get_mut(key);
+            }
+            Operation::Retain(ref filter) => {
+                store.retain(&*filter.0);
+            }
+            Operation::Extend(ref entries) => {
+                store.reserve(entries.len());
+                for (key, value) in entries {
+                    store.insert(key.clone(), value.clone());
+                }
+            }
+            Operation::ReplaceAll(ref data) => {
+                store.replace_all(data.clone());
+            }
             Operation::Purge => {
-                self.data.clear();
+                store.clear();
+            }
+            Operation::Sweep(now) => {
+                store.remove_expired(*now);
+            }
+            Operation::Report(enabled) => {
+                store.report_missed(*enabled);
             }
             Operation::MarkReady => {
-                self.ready = true;
+                store.mark_ready();
             }
         }
     }
 
-    fn absorb_second(&mut self, operation: Operation<K, V>, _other: &Self) {
-        match operation {
+    /// Applies the operation to the second copy, consuming it.
+    pub(crate) fn apply_second<M: Store<K, V, Data = D>>(self, store: &mut M) {
+        match self {
             Operation::Add(key, value) => {
-                self.data.insert(key, value);
+                store.insert(key, value);
             }
-            Operation::Replace(key, value) => {
-                if let Some(v) = self.data.get_mut(&key) {
-                    *v = value.clone();
+            Operation::AddWithTtl(key, value, at) => {
+                store.insert(key.clone(), value);
+                store.set_expiry(key, at);
+            }
+            Operation::AddIfAbsent(key, value) => {
+                if store.get(&key).is_none() {
+                    store.insert(key, value);
                 }
             }
+            Operation::Replace(key, value, now) => match store.get_live_mut(&key, now) {
+                Some(v) => *v = value,
+                None => store.missed(&key),
+            },
+            Operation::ReplaceOrAdd(key, value, now) => match store.get_live_mut(&key, now) {
+                Some(v) => *v = value,
+                None => store.insert(key, value),
+            },
+            Operation::Upsert(key, default, modifier, now) => match store.get_live_mut(&key, now) {
+                Some(v) => modifier.modify(v),
+                None => store.insert(key, default),
+            },
             Operation::Remove(key) => {
-                self.data.remove(&key);
+                store.remove(&key);
+            }
+            Operation::RemoveIf(key, predicate) => {
+                if store.get(&key).is_some_and(|v| predicate.test(v)) {
+                    store.remove(&key);
+                }
             }
-            Operation::Modify(key, modifier) => {
-                if let Some(v) = self.data.get_mut(&key) {
-                    modifier.modify(v);
+            Operation::Move(from, to) => {
+                let expiry = store.expiry(&from);
+                if let Some(v) = store.remove(&from) {
+                    store.insert(to.clone(), v);
+                    if let Some(at) = expiry {
+                        store.set_expiry(to, at);
+                    }
                 }
             }
+            Operation::Modify(key, modifier, now) => match store.get_live_mut(&key, now) {
+                Some(v) => modifier.modify(v),
+                None => store.missed(&key),
+            },
+            Operation::Touch(key) => {
+                store.get_mut(&key);
+            }
+            Operation::Retain(filter) => {
+                store.retain(&*filter.0);
+            }
+            Operation::Extend(entries) => {
+                store.reserve(entries.len());
+                for (key, value) in entries {
+                    store.insert(key, value);
+                }
+            }
+            Operation::ReplaceAll(data) => {
+                store.replace_all(data);
+            }
             Operation::Purge => {
-                self.data.clear();
+                store.clear();
+            }
+            Operation::Sweep(now) => {
+                store.remove_expired(now);
+            }
+            Operation::Report(enabled) => {
+                store.report_missed(enabled);
             }
             Operation::MarkReady => {
-                self.ready = true;
+                store.mark_ready();
             }
         }
     }
+}
+
+impl<K, V, S> Absorb<MapOperation<K, V, S>> for Inner<K, V, S>
+where
+    K: Eq + Hash + Clone,
+    V: Clone,
+    S: BuildHasher + Clone,
+{
+    fn absorb_first(&mut self, operation: &mut MapOperation<K, V, S>, _other: &Self) {
+        operation.apply_first(self);
+        self.settle();
+    }
+
+    fn absorb_second(&mut self, operation: MapOperation<K, V, S>, _other: &Self) {
+        operation.apply_second(self);
+        self.settle();
+    }
 
     fn sync_with(&mut self, first: &Self) {
         self.data = first.data.clone();
+        self.expiry = first.expiry.clone();
+        self.eviction = first.eviction.clone();
+        self.missed = first.missed.clone();
         self.ready = first.ready;
     }
 }
 
-pub(super) struct Modifier<V: ?Sized>(Box<dyn Fn(&mut V) -> () + Send>);
+pub(super) struct Modifier<V: ?Sized>(pub(super) Box<dyn Fn(&mut V) + Send>);
 
 impl<V: ?Sized> Modifier<V> {
-    fn modify(&self, value: &mut V) -> () {
+    fn modify(&self, value: &mut V) {
         (*self.0)(value)
     }
 }
@@ -269,3 +1016,31 @@ impl<V: ?Sized> fmt::Debug for Modifier<V> {
             .finish()
     }
 }
+
+type FilterFn<K, V> = dyn Fn(&K, &V) -> bool + Send;
+
+pub(super) struct Filter<K, V>(pub(super) Box<FilterFn<K, V>>);
+
+impl<K, V> fmt::Debug for Filter<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Filter")
+            .field(&format_args!("{:p}", &*self.0 as *const _))
+            .finish()
+    }
+}
+
+pub(super) struct Predicate<V: ?Sized>(pub(super) Box<dyn Fn(&V) -> bool + Send>);
+
+impl<V: ?Sized> Predicate<V> {
+    pub(crate) fn test(&self, value: &V) -> bool {
+        (*self.0)(value)
+    }
+}
+
+impl<V: ?Sized> fmt::Debug for Predicate<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Predicate")
+            .field(&format_args!("{:p}", &*self.0 as *const _))
+            .finish()
+    }
+}
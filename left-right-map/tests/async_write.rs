@@ -0,0 +1,68 @@
+#![cfg(feature = "tokio")]
+
+use left_right_map::async_write::{AsyncWriteHandle, AsyncWriterConfig, WriterClosed};
+use std::time::Duration;
+
+#[tokio::test]
+async fn publish() {
+    let (w, r) = left_right_map::new::<u32, u32>();
+    let config = AsyncWriterConfig {
+        publish_interval: Duration::from_secs(60),
+        max_pending: 1000,
+    };
+    let (w, task) = AsyncWriteHandle::spawn(w, config);
+
+    let mut tasks = tokio::task::JoinSet::new();
+    for i in 0..10 {
+        let w = w.clone();
+        tasks.spawn(async move {
+            w.insert(i, i).unwrap();
+            w.upsert(100, 1, |v| *v += 1).unwrap();
+        });
+    }
+    while let Some(res) = tasks.join_next().await {
+        res.unwrap();
+    }
+    assert!(r.enter().is_none());
+
+    w.publish().await.unwrap();
+    assert_eq!(r.len(), 11);
+    assert_eq!(*r.get(&100).unwrap(), 10);
+
+    // dropping last handle stops the writer, which publishes the rest
+    w.remove(100).unwrap();
+    let read_handle = w.read_handle().clone();
+    drop(w);
+    let mut w = task.await.unwrap();
+    assert!(!w.has_pending());
+    assert!(!read_handle.contains_key(&100));
+    w.insert(200, 200).publish();
+    assert_eq!(r.len(), 11);
+}
+
+#[tokio::test]
+async fn publish_policies() {
+    let (w, r) = left_right_map::new::<u32, u32>();
+    let config = AsyncWriterConfig {
+        publish_interval: Duration::from_millis(10),
+        max_pending: 5,
+    };
+    let (w, task) = AsyncWriteHandle::spawn(w, config);
+
+    // after max_pending operations
+    for i in 0..5 {
+        w.insert(i, i).unwrap();
+    }
+    let epoch = r.wait_for_publish_async(0).await.unwrap();
+    assert_eq!(r.len(), 5);
+
+    // after publish interval
+    w.insert(5, 5).unwrap();
+    r.wait_for_publish_async(epoch).await.unwrap();
+    assert_eq!(r.len(), 6);
+
+    task.abort();
+    let _ = task.await;
+    assert_eq!(w.insert(6, 6), Err(WriterClosed));
+    assert_eq!(w.publish().await, Err(WriterClosed));
+}
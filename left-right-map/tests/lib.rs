@@ -1,3 +1,6 @@
+#![cfg(not(loom))]
+
+use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
 
@@ -19,8 +22,8 @@ fn it_works() {
     // the map is uninitialized, so all lookups should return None
     assert_match!(r.get(&x.0), None);
     assert_match!(r.enter(), None);
-    assert_eq!(r.is_empty(), true);
-    assert_eq!(r.contains_key(&x.0), false);
+    assert!(r.is_empty());
+    assert!(!r.contains_key(&x.0));
 
     w.insert(x.0, x);
 
@@ -32,12 +35,12 @@ fn it_works() {
     // but after the swap, the record is there!
     assert_eq!(r.len(), 1);
     assert_eq!(*r.get(&x.0).unwrap(), x);
-    assert_eq!(r.contains_key(&x.0), true);
-    assert_match!(r.enter().unwrap().get(&x.0), Some(x));
+    assert!(r.contains_key(&x.0));
+    assert_eq!(r.enter().unwrap().get(&x.0), Some(&x));
 
     // non-existing records return None
     assert_match!(r.get(&'y'), None);
-    assert_eq!(r.contains_key(&'y'), false);
+    assert!(!r.contains_key(&'y'));
 
     // if we purge, the readers still see the values
     w.purge();
@@ -47,7 +50,7 @@ fn it_works() {
     // but once we refresh, things will be empty
     w.publish();
     assert_match!(r.get(&x.0), None);
-    assert_eq!(r.is_empty(), true);
+    assert!(r.is_empty());
 }
 
 #[test]
@@ -239,6 +242,60 @@ fn modify() {
     assert_eq!(r.get(&2).unwrap().deref(), &"b");
 }
 
+#[test]
+fn insert_if_absent() {
+    let (mut w, r) = left_right_map::new();
+    w.insert(1, "a");
+    w.insert_if_absent(1, "x");
+    w.insert_if_absent(2, "b");
+    w.publish();
+
+    assert_eq!(r.get(&1).unwrap().deref(), &"a");
+    assert_eq!(r.get(&2).unwrap().deref(), &"b");
+
+    // applied against the second copy as well
+    w.insert_if_absent(2, "y");
+    w.publish();
+    w.publish();
+    assert_eq!(r.get(&2).unwrap().deref(), &"b");
+}
+
+#[test]
+fn upsert() {
+    let (mut w, r) = left_right_map::new();
+    w.upsert(1, 1, |v| *v += 1);
+    w.publish();
+    assert_eq!(*r.get(&1).unwrap(), 1);
+
+    w.upsert(1, 1, |v| *v += 1);
+    w.upsert(1, 1, |v| *v += 1);
+    w.publish();
+    assert_eq!(*r.get(&1).unwrap(), 3);
+
+    // both copies have to end up identical
+    w.publish();
+    assert_eq!(*r.get(&1).unwrap(), 3);
+}
+
+#[test]
+fn remove_if() {
+    let (mut w, r) = left_right_map::new();
+    w.insert(1, 10);
+    w.insert(2, 20);
+    w.publish();
+
+    w.remove_if(1, |v| *v > 15);
+    w.remove_if(2, |v| *v > 15);
+    w.remove_if(3, |_| true);
+    w.publish();
+
+    assert_eq!(*r.get(&1).unwrap(), 10);
+    assert_match!(r.get(&2), None);
+
+    w.publish();
+    assert_eq!(r.len(), 1);
+}
+
 #[test]
 fn example_usage() {
     // map storing <partition, offset>
@@ -290,3 +347,728 @@ impl FinishingOffset {
         }
     }
 }
+
+#[test]
+fn multi() {
+    let (mut w, r) = left_right_map::new_multi();
+    w.insert(1, "a");
+    w.insert(1, "b");
+    w.insert(1, "a");
+    w.insert(2, "c");
+    w.publish();
+
+    {
+        let values = r.get(&1).unwrap();
+        let mut values: Vec<&&str> = values.iter().collect();
+        values.sort();
+        assert_eq!(values, vec![&"a", &"a", &"b"]);
+    }
+
+    // removes only a single occurrence
+    w.remove_value(1, "a");
+    w.remove_value(2, "c");
+    w.publish();
+
+    {
+        let values = r.get(&1).unwrap();
+        assert_eq!(values.len(), 2);
+        assert!(values.contains(&"a"));
+        assert!(values.contains(&"b"));
+    }
+    // empty bag removes the key
+    assert!(!r.contains_key(&2));
+
+    w.clear_key(1);
+    w.publish();
+    assert!(r.is_empty());
+
+    // second copy has to converge as well
+    w.insert(3, "d");
+    w.publish();
+    w.publish();
+    assert_eq!(r.get(&3).unwrap().as_slice(), &["d"]);
+}
+
+#[test]
+fn transaction() {
+    use left_right_map::transaction::{Transaction, TransactionError};
+
+    let (mut w, r) = left_right_map::new();
+    w.insert("a", 1);
+    w.publish();
+
+    // move value from "a" to "b", only if "a" exists
+    let tx = Transaction::new()
+        .require_present("a")
+        .require_absent("b")
+        .move_value("a", "b");
+    w.commit(tx).unwrap();
+    w.publish();
+
+    assert!(!r.contains_key("a"));
+    assert_eq!(*r.get("b").unwrap(), 1);
+
+    // failed precondition appends nothing
+    let tx = Transaction::new()
+        .require_present("a")
+        .insert("c", 3)
+        .remove("b");
+    assert_eq!(w.commit(tx).unwrap_err(), TransactionError::KeyMissing("a"));
+    assert!(!w.has_pending());
+
+    let tx = Transaction::new().require("b", |v| *v > 1).remove("b");
+    assert_eq!(
+        w.commit(tx).unwrap_err(),
+        TransactionError::PredicateFailed("b")
+    );
+
+    // works through shared writer as well, and converges on both copies
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+    let tx = Transaction::new()
+        .require("b", |v| *v == 1)
+        .modify("b", |v| *v += 1)
+        .insert("c", 3);
+    w.commit(tx).unwrap();
+    w.publish();
+    w.publish();
+    assert_eq!(*r.get("b").unwrap(), 2);
+    assert_eq!(*r.get("c").unwrap(), 3);
+}
+
+#[test]
+fn transaction_sees_pending() {
+    use left_right_map::transaction::{Transaction, TransactionError};
+
+    let move_a = || {
+        Transaction::new()
+            .require_present("a")
+            .move_value("a", "b")
+            .insert("c", 3)
+    };
+
+    // operations applied before the first publish count as well
+    let (mut w, r) = left_right_map::new();
+    w.insert("a", 1);
+    w.commit(Transaction::new().require_present("a")).unwrap();
+    w.publish();
+    w.remove("a");
+    assert_eq!(
+        w.commit(move_a()).unwrap_err(),
+        TransactionError::KeyMissing("a")
+    );
+    w.publish();
+    assert_eq!(r.len(), 0);
+
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+    w.insert("a", 1);
+    w.publish();
+    w.remove("a");
+    assert_eq!(
+        w.commit(move_a()).unwrap_err(),
+        TransactionError::KeyMissing("a")
+    );
+    w.publish();
+    assert_eq!(r.len(), 0);
+
+    let (mut w, r) = left_right_map::new_ordered();
+    w.insert("a", 1);
+    w.commit(Transaction::new().require_present("a")).unwrap();
+    w.publish();
+    w.remove("a");
+    assert_eq!(
+        w.commit(move_a()).unwrap_err(),
+        TransactionError::KeyMissing("a")
+    );
+    w.publish();
+    assert_eq!(r.len(), 0);
+}
+
+#[test]
+fn shared_values() {
+    let (mut w, r) = left_right_map::new_shared();
+    let value = Arc::new(vec![1, 2, 3]);
+    w.insert(1, value.clone());
+    w.publish();
+    w.publish();
+
+    // both copies point to the same allocation as the original value
+    assert_eq!(Arc::strong_count(&value), 3);
+    assert!(Arc::ptr_eq(&r.get(&1).unwrap(), &value));
+
+    w.remove(1);
+    w.publish();
+    w.publish();
+    assert_eq!(Arc::strong_count(&value), 1);
+}
+
+#[test]
+fn wait_for_publish() {
+    let (mut w, r) = left_right_map::new();
+    assert_eq!(r.epoch(), 0);
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let reader = r.clone();
+    let t = std::thread::spawn(move || {
+        let epoch = reader.wait_for_publish(0).unwrap();
+        assert_eq!(epoch, 1);
+        // data of the epoch has to be visible already
+        assert_eq!(*reader.get(&1).unwrap(), "a");
+        tx.send(()).unwrap();
+        reader.wait_for_publish(epoch)
+    });
+
+    w.insert(1, "a");
+    w.publish();
+    rx.recv().unwrap();
+
+    // writer gone, so the second wait wakes up with nothing
+    drop(w);
+    assert_eq!(t.join().unwrap(), None);
+    assert_eq!(r.epoch(), 1);
+}
+
+#[tokio::test]
+async fn wait_for_publish_async() {
+    let (w, r) = left_right_map::new();
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+
+    let wait = tokio::spawn(r.wait_for_publish_async(r.epoch()));
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    assert!(!wait.is_finished());
+
+    w.insert(1, "a");
+    w.publish();
+    assert_eq!(wait.await.unwrap(), Some(1));
+    assert_eq!(r.wait_for_publish_async(0).await, Some(1));
+}
+
+#[derive(Default)]
+struct IdentityHasher(u64);
+
+impl std::hash::Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 << 8) | u64::from(byte);
+        }
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.0 = i;
+    }
+}
+
+type IdentityState = std::hash::BuildHasherDefault<IdentityHasher>;
+
+#[test]
+fn with_hasher() {
+    let (mut w, r) = left_right_map::with_hasher::<u64, &str, _>(IdentityState::default());
+    w.insert(1, "a");
+    w.insert(2, "b");
+    w.upsert(1, "x", |v| *v = "c");
+    w.publish();
+    w.publish();
+
+    assert_eq!(*r.get(&1).unwrap(), "c");
+    let map = r.enter().unwrap();
+    let mut keys: Vec<&u64> = map.iter().map(|(k, _)| k).collect();
+    keys.sort();
+    assert_eq!(keys, vec![&1, &2]);
+}
+
+#[test]
+fn with_capacity_and_hasher() {
+    let (w, r) =
+        left_right_map::with_capacity_and_hasher::<u64, u64, _>(128, IdentityState::default());
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+    for i in 0..100 {
+        w.insert(i, i * 2);
+    }
+    w.publish();
+
+    assert_eq!(r.len(), 100);
+    assert_eq!(*r.get(&50).unwrap(), 100);
+}
+
+#[test]
+fn ordered() {
+    let (mut w, r) = left_right_map::new_ordered();
+    assert!(r.enter().is_none());
+
+    for bucket in [30u64, 10, 50, 20, 40] {
+        w.insert(bucket, bucket * 10);
+    }
+    w.upsert(10, 0, |v| *v += 1);
+    w.remove_if(50, |v| *v == 500);
+    w.publish();
+
+    {
+        let map = r.enter().unwrap();
+        let keys: Vec<&u64> = map.iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, vec![&10, &20, &30, &40]);
+        assert_eq!(map.first(), Some((&10, &101)));
+        assert_eq!(map.last(), Some((&40, &400)));
+
+        let range: Vec<(&u64, &u64)> = map.range(15..=30).collect();
+        assert_eq!(range, vec![(&20, &200), (&30, &300)]);
+        assert_eq!(map.range(41..).count(), 0);
+    }
+
+    // second copy converges to the same ordered content
+    w.remove(10);
+    w.publish();
+    w.publish();
+    assert_eq!(r.len(), 3);
+    assert_eq!(r.enter().unwrap().first(), Some((&20, &200)));
+    assert_eq!(*r.get(&40).unwrap(), 400);
+}
+
+#[test]
+fn publish_after_max_pending() {
+    let (mut w, r) = left_right_map::new();
+    w.set_max_pending(Some(3));
+    w.insert(1, "a");
+    w.insert(2, "b");
+    assert!(r.enter().is_none());
+    w.insert(3, "c");
+    assert!(!w.has_pending());
+    assert_eq!(r.len(), 3);
+
+    // transaction counts each of its operations
+    let tx = left_right_map::transaction::Transaction::new()
+        .insert(4, "d")
+        .insert(5, "e")
+        .remove(1);
+    w.commit(tx).unwrap();
+    assert_eq!(r.len(), 4);
+
+    // pending operations are counted across clones of the shared writer
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+    let mut w2 = w.clone();
+    w.insert(6, "f");
+    w2.insert(7, "g");
+    assert_eq!(r.len(), 4);
+    w2.insert(8, "h");
+    assert_eq!(r.len(), 7);
+}
+
+#[test]
+fn publish_on_batch_drop() {
+    let (mut w, r) = left_right_map::new();
+    {
+        let mut batch = w.batch();
+        batch.insert(1, "a").insert(2, "b");
+        assert!(r.enter().is_none());
+    }
+    assert_eq!(r.len(), 2);
+
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+    {
+        let mut batch = w.batch();
+        batch.remove(1);
+    }
+    assert!(!r.contains_key(&1));
+}
+
+#[test]
+fn publish_every() {
+    let (w, r) = left_right_map::new();
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+    let timer = w.publish_every(std::time::Duration::from_millis(5));
+
+    let epoch = r.epoch();
+    w.insert(1, "a");
+    assert!(r.wait_for_publish(epoch).is_some());
+    assert_eq!(*r.get(&1).unwrap(), "a");
+
+    drop(w);
+    timer.join().unwrap();
+    assert_eq!(r.wait_for_publish(r.epoch()), None);
+}
+
+#[test]
+fn ttl() {
+    use left_right_map::transaction::Transaction;
+    use std::time::Duration;
+
+    let (mut w, r) = left_right_map::new();
+    w.insert_with_ttl(1, "expired", Duration::ZERO);
+    w.insert_with_ttl(2, "live", Duration::from_secs(3600));
+    w.insert(3, "forever");
+    w.insert_with_ttl(4, "expired", Duration::ZERO);
+    // plain insert drops the deadline
+    w.insert(4, "renewed");
+    w.publish();
+
+    assert!(r.get(&1).is_none());
+    assert!(!r.contains_key(&1));
+    assert_eq!(*r.get(&2).unwrap(), "live");
+    assert_eq!(r.len(), 3);
+    {
+        let map = r.enter().unwrap();
+        let mut values: Vec<&str> = map.values().copied().collect();
+        values.sort();
+        assert_eq!(values, vec!["forever", "live", "renewed"]);
+        assert_eq!(map.iter().count(), 3);
+    }
+
+    // moved entry keeps its deadline
+    w.insert_with_ttl(5, "expired", Duration::ZERO);
+    w.commit(Transaction::new().move_value(5, 6)).unwrap();
+    w.publish();
+    assert!(r.get(&6).is_none());
+
+    // writer operations see expired entries until they are swept, preconditions do not
+    w.commit(Transaction::new().require_absent(1).require_absent(6))
+        .unwrap();
+    w.insert_if_absent(1, "ignored");
+    w.publish();
+    assert!(r.get(&1).is_none());
+    w.sweep();
+    w.insert_if_absent(1, "inserted");
+    w.publish();
+    assert_eq!(*r.get(&1).unwrap(), "inserted");
+    assert_eq!(r.len(), 4);
+}
+
+#[test]
+fn bounded_lru() {
+    use left_right_map::bounded::{Capacity, EvictionPolicy};
+
+    let (mut w, r) = left_right_map::new_bounded(Capacity::new(2, EvictionPolicy::Lru));
+    w.insert(1, "a").insert(2, "b").touch(1).insert(3, "c");
+    w.publish();
+    assert_eq!(r.len(), 2);
+    assert!(!r.contains_key(&2));
+
+    // modification counts as a use as well, and both copies evict the same key
+    w.modify(1, |v| *v = "aa").insert(4, "d");
+    w.publish();
+    w.publish();
+    let mut keys: Vec<i32> = r.enter().unwrap().iter().map(|(k, _)| *k).collect();
+    keys.sort();
+    assert_eq!(keys, vec![1, 4]);
+}
+
+#[test]
+fn bounded_lfu_with_weigher() {
+    use left_right_map::bounded::{Capacity, EvictionPolicy};
+
+    let (mut w, r) = left_right_map::new_bounded(Capacity::new(3, EvictionPolicy::Lfu));
+    w.insert(1, "a").touch(1).touch(1);
+    w.insert(2, "b").insert(3, "c").touch(3).insert(4, "d");
+    w.publish();
+    let mut keys: Vec<i32> = r.enter().unwrap().iter().map(|(k, _)| *k).collect();
+    keys.sort();
+    assert_eq!(keys, vec![1, 3, 4]);
+
+    let capacity =
+        Capacity::new(10, EvictionPolicy::Lru).with_weigher(6, |_: &i32, v: &String| v.len());
+    let (mut w, r) = left_right_map::new_bounded(capacity);
+    w.insert(1, "abc".to_string()).insert(2, "de".to_string());
+    w.publish();
+    assert_eq!(r.len(), 2);
+
+    // growing a value in place pushes the map over its weight
+    w.modify(2, |v| v.push_str("fg"));
+    w.publish();
+    w.publish();
+    assert!(!r.contains_key(&1));
+    assert_eq!(*r.get(&2).unwrap(), "defg");
+}
+
+#[test]
+fn bounded_lfu_counts_insert_once() {
+    use left_right_map::bounded::{Capacity, EvictionPolicy};
+    use left_right_map::write::WriteHandle;
+
+    // inserting a fresh key is a single use, whichever operation inserts it
+    let paths: [fn(&mut WriteHandle<i32, i32>); 3] = [
+        |w| {
+            w.insert(1, 1);
+        },
+        |w| {
+            w.upsert(1, 1, |v| *v += 1);
+        },
+        |w| {
+            w.update_or_insert(1, 1);
+        },
+    ];
+    for path in paths {
+        let (mut w, r) = left_right_map::new_bounded(Capacity::new(1, EvictionPolicy::Lfu));
+        path(&mut w);
+        w.insert(2, 2);
+        w.publish();
+        w.publish();
+        let keys: Vec<i32> = r.enter().unwrap().iter().map(|(k, _)| *k).collect();
+        assert_eq!(keys, vec![2]);
+    }
+}
+
+#[test]
+fn owned_reads() {
+    let (mut w, r) = left_right_map::new();
+    assert_eq!(r.get_cloned(&1), None);
+    assert!(r.map_values(|_, v: &String| v.len()).is_empty());
+
+    w.insert(1, "a".to_string()).insert(2, "bb".to_string());
+    w.publish();
+
+    let value: String = r.get_cloned(&1).unwrap();
+    // guard is released, so writer can publish twice while the value is still held
+    w.insert(1, "c".to_string());
+    w.publish();
+    w.publish();
+    assert_eq!(value, "a");
+    assert_eq!(r.get_and(&2, |v| v.len()), Some(2));
+    assert_eq!(r.get_and(&3, |v| v.len()), None);
+
+    let mut lengths = r.map_values(|k, v| (*k, v.len()));
+    lengths.sort();
+    assert_eq!(lengths, vec![(1, 1), (2, 2)]);
+}
+
+#[test]
+fn detect_long_holds() {
+    let (mut w, mut r) = left_right_map::new();
+    w.insert(1, "a");
+    w.publish();
+    assert_eq!(r.long_holds(), 0);
+
+    r.detect_long_holds(std::time::Duration::from_millis(5));
+    let reader = r.clone();
+    assert_eq!(*r.get(&1).unwrap(), "a");
+    {
+        let _guard = reader.get_monitored(&1).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    {
+        let _map = reader.enter().unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    // count is shared between clones
+    assert_eq!(r.long_holds(), 2);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn read_handle_factory() {
+    use left_right_map::read::ReadHandleCache;
+
+    let (mut w, r) = left_right_map::new();
+    w.insert(1, "a");
+    w.publish();
+
+    let factory = Arc::new(r.factory());
+    let cache = Arc::new(ReadHandleCache::new(r.factory()));
+    let mut tasks = tokio::task::JoinSet::new();
+    for _ in 0..8 {
+        let factory = factory.clone();
+        let cache = cache.clone();
+        tasks.spawn(async move {
+            assert_eq!(factory.handle().get_cloned(&1), Some("a"));
+            tokio::task::yield_now().await;
+            cache.with(|r| {
+                // nested use on the same thread works as well
+                assert_eq!(cache.with(|r| r.len()), 1);
+                r.get_cloned(&1)
+            })
+        });
+    }
+    while let Some(value) = tasks.join_next().await {
+        assert_eq!(value.unwrap(), Some("a"));
+    }
+}
+
+#[test]
+fn bulk_operations() {
+    let (mut w, r) = left_right_map::new();
+    w.extend((0..100).map(|i| (i, i * 10)));
+    w.publish();
+    assert_eq!(r.len(), 100);
+
+    w.retain(|k, v| k % 2 == 0 && *v < 500);
+    w.publish();
+    {
+        let map = r.enter().unwrap();
+        let mut keys: Vec<i32> = map.keys().copied().collect();
+        keys.sort();
+        assert_eq!(keys, (0..50).step_by(2).collect::<Vec<_>>());
+    }
+
+    let fresh: HashMap<i32, i32> = [(1, 1), (2, 2)].into_iter().collect();
+    w.replace_all(fresh);
+    w.publish();
+    w.publish();
+    let mut entries = r.map_values(|k, v| (*k, *v));
+    entries.sort();
+    assert_eq!(entries, vec![(1, 1), (2, 2)]);
+
+    // ordered map supports the same bulk operations
+    let (mut w, r) = left_right_map::new_ordered();
+    w.extend([(3, "c"), (1, "a"), (2, "b")]);
+    w.retain(|k, _| *k != 2);
+    w.publish();
+    assert_eq!(r.enter().unwrap().keys().collect::<Vec<_>>(), vec![&1, &3]);
+
+    w.replace_all([(5, "e"), (4, "d")].into_iter().collect());
+    w.publish();
+    w.publish();
+    assert_eq!(r.enter().unwrap().keys().collect::<Vec<_>>(), vec![&4, &5]);
+}
+
+#[test]
+fn bounded_replace_all() {
+    use left_right_map::bounded::{Capacity, EvictionPolicy};
+
+    let (mut w, r) = left_right_map::new_bounded(Capacity::new(2, EvictionPolicy::Lru));
+    w.insert_with_ttl(0, 0, std::time::Duration::ZERO);
+    w.replace_all((1..=3).map(|i| (i, i)).collect());
+    w.publish();
+    let first: Vec<i32> = r.enter().unwrap().keys().copied().collect();
+    assert_eq!(first.len(), 2);
+
+    // the other copy gets the moved map, yet evicts the same key
+    w.publish();
+    let second: Vec<i32> = r.enter().unwrap().keys().copied().collect();
+    assert_eq!(first, second);
+}
+
+#[test]
+fn update_or_insert() {
+    use std::time::Duration;
+
+    let (mut w, r) = left_right_map::new();
+    w.update(1, "ignored");
+    w.update_or_insert(2, "inserted");
+    w.insert_with_ttl(3, "old", Duration::from_secs(3600));
+    w.insert_with_ttl(4, "expired", Duration::ZERO);
+    w.publish();
+    assert_eq!(r.get_cloned(&1), None);
+    assert_eq!(r.get_cloned(&2), Some("inserted"));
+
+    // live entry is replaced, expired one is inserted anew without its past deadline
+    w.update_or_insert(3, "new");
+    w.update_or_insert(4, "renewed");
+    w.sweep();
+    w.publish();
+    assert_eq!(r.get_cloned(&3), Some("new"));
+    assert_eq!(r.get_cloned(&4), Some("renewed"));
+    w.publish();
+    assert_eq!(r.get_cloned(&4), Some("renewed"));
+    assert_eq!(r.len(), 3);
+}
+
+#[test]
+fn strict_updates() {
+    let (mut w, r) = left_right_map::new();
+    w.set_strict_updates(true);
+    w.update(1, 1);
+    w.insert(2, 2);
+    w.update(2, 20);
+    w.modify(3, |v| *v += 1);
+    let report = w.publish();
+    assert_eq!(report.missing(), &[1, 3]);
+    assert_eq!(r.get_cloned(&2), Some(20));
+
+    // every publish reports only its own batch, on both copies of the map
+    w.update(2, 21);
+    assert!(w.publish().is_empty());
+    w.remove(2)
+        .update(2, 22)
+        .update_or_insert(4, 4)
+        .update(4, 40);
+    assert_eq!(w.publish().into_missing(), vec![2]);
+    assert!(w.publish().is_empty());
+
+    let tx = left_right_map::transaction::Transaction::new().update(5, 5);
+    w.commit(tx).unwrap();
+    assert_eq!(w.publish().missing(), &[5]);
+
+    // expired entries are missing for updates, even before they are swept
+    w.insert_with_ttl(7, 7, std::time::Duration::ZERO);
+    w.update(7, 70).modify(7, |v| *v += 1);
+    assert_eq!(w.publish().missing(), &[7, 7]);
+    assert_eq!(r.get_cloned(&7), None);
+
+    w.set_strict_updates(false);
+    w.update(6, 6);
+    assert!(w.publish().is_empty());
+    assert_eq!(r.get_cloned(&4), Some(40));
+}
+
+#[test]
+fn writer_view() {
+    use left_right_map::transaction::{Transaction, TransactionError};
+
+    let (mut w, r) = left_right_map::new();
+    assert!(w.view().is_none());
+    w.enable_view();
+    w.insert("a", 1).insert("b", 2);
+    assert_eq!(w.view().unwrap().get("a"), Some(&1));
+    assert_eq!(r.get_cloned("a"), None);
+    w.publish();
+
+    w.remove("a").modify("b", |v| *v += 10).insert("c", 3);
+    {
+        let view = w.view().unwrap();
+        assert_eq!(view.get("a"), None);
+        assert_eq!(view.get("b"), Some(&12));
+        assert_eq!(view.len(), 2);
+        let mut entries: Vec<_> = view.iter().map(|(k, v)| (*k, *v)).collect();
+        entries.sort();
+        assert_eq!(entries, vec![("b", 12), ("c", 3)]);
+    }
+    assert_eq!(r.get_cloned("b"), Some(2));
+
+    // transactions see pending operations
+    let tx = Transaction::new().require_present("c").move_value("c", "d");
+    w.commit(tx).unwrap();
+    let tx = Transaction::new().require_present("a").remove("b");
+    assert_eq!(w.commit(tx).unwrap_err(), TransactionError::KeyMissing("a"));
+    assert_eq!(w.view().unwrap().get("d"), Some(&3));
+
+    w.purge().insert("e", 5);
+    assert_eq!(
+        w.view().unwrap().iter().collect::<Vec<_>>(),
+        vec![(&"e", &5)]
+    );
+    w.publish();
+    assert_eq!(r.len(), 1);
+    assert_eq!(w.view().unwrap().get("e"), Some(&5));
+
+    w.retain(|_, v| *v > 5)
+        .insert_with_ttl("f", 6, std::time::Duration::ZERO);
+    assert!(w.view().unwrap().is_empty());
+}
+
+#[test]
+fn view_sees_writes_before_first_publish() {
+    use left_right_map::transaction::Transaction;
+
+    // left-right applies them right away, so they are not pending
+    let (mut w, _r) = left_right_map::new();
+    w.insert(1, 1);
+    w.enable_view();
+    assert_eq!(w.view().unwrap().get(&1), Some(&1));
+    assert!(w.commit(Transaction::new().require_present(1)).is_ok());
+}
+
+#[test]
+fn transaction_skips_expired() {
+    use left_right_map::transaction::Transaction;
+
+    let (mut w, _r) = left_right_map::new();
+    w.insert_with_ttl(1, 1, std::time::Duration::ZERO);
+    // expired entries are missing for preconditions, with or without the view
+    assert!(w.commit(Transaction::new().require_absent(1)).is_ok());
+    w.publish();
+    assert!(w.commit(Transaction::new().require_absent(1)).is_ok());
+
+    w.enable_view();
+    w.insert_with_ttl(2, 2, std::time::Duration::ZERO);
+    assert_eq!(w.view().unwrap().get(&2), None);
+    assert!(w.commit(Transaction::new().require_absent(2)).is_ok());
+}
@@ -0,0 +1,102 @@
+//! Loom models of concurrent readers and writers. Run with
+//! `RUSTFLAGS="--cfg loom" cargo test -p left-right-map --test loom --release`.
+#![cfg(loom)]
+
+use left_right_map::transaction::Transaction;
+use left_right_map::write::SharedWriteHandle;
+use loom::thread;
+
+/// Every batch writes the same value under both keys, so a reader seeing the keys differ
+/// would have observed a half-applied batch.
+fn assert_whole_batch(r: &left_right_map::read::ReadHandle<u8, u8>) {
+    if let Some(map) = r.enter() {
+        assert_eq!(map.get(&0), map.get(&1));
+    }
+}
+
+#[test]
+fn publish_exposes_whole_batches() {
+    loom::model(|| {
+        let (mut w, r) = left_right_map::new::<u8, u8>();
+        let reader = r.clone();
+        let t = thread::spawn(move || {
+            assert_whole_batch(&reader);
+            assert_whole_batch(&reader);
+        });
+
+        w.insert(0, 1).insert(1, 1);
+        w.publish();
+        w.update(0, 2).update(1, 2);
+        w.publish();
+
+        t.join().unwrap();
+        assert_eq!(*r.get(&0).unwrap(), 2);
+    });
+}
+
+#[test]
+fn epoch_is_published_after_data() {
+    loom::model(|| {
+        let (mut w, r) = left_right_map::new::<u8, u64>();
+        let t = thread::spawn(move || {
+            let epoch = r.epoch();
+            // every publish writes its own epoch, so data can not be older than the epoch
+            let value = r.get_cloned(&0).unwrap_or(0);
+            assert!(value >= epoch, "epoch {} but value {}", epoch, value);
+        });
+
+        for epoch in 1..=2 {
+            w.insert(0, epoch);
+            w.publish();
+        }
+        t.join().unwrap();
+    });
+}
+
+#[test]
+fn wait_for_publish_wakes_up() {
+    loom::model(|| {
+        let (mut w, r) = left_right_map::new::<u8, u8>();
+        let reader = r.clone();
+        let t = thread::spawn(move || {
+            assert_eq!(reader.wait_for_publish(0), Some(1));
+            assert_eq!(*reader.get(&0).unwrap(), 1);
+        });
+
+        w.insert(0, 1);
+        w.publish();
+        t.join().unwrap();
+
+        drop(w);
+        assert_eq!(r.wait_for_publish(1), None);
+    });
+}
+
+#[test]
+fn shared_writer_commits_whole_transactions() {
+    loom::model(|| {
+        let (w, r) = left_right_map::new::<u8, u8>();
+        let mut w = SharedWriteHandle::from(w);
+        // commit publishes a map, that was never published, keep the model to the commits
+        w.publish();
+        let writers: Vec<_> = (1..=2)
+            .map(|value| {
+                let mut writer = w.clone();
+                thread::spawn(move || {
+                    let tx = Transaction::new().insert(0, value).insert(1, value);
+                    writer.commit(tx).unwrap();
+                    writer.publish();
+                })
+            })
+            .collect();
+
+        assert_whole_batch(&r);
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        w.publish();
+        assert_whole_batch(&r);
+        assert!(r.contains_key(&0));
+    });
+}
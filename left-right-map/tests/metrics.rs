@@ -0,0 +1,48 @@
+#![cfg(feature = "metrics")]
+
+use left_right_map::metrics::{PublishMetrics, Recorder, TracingRecorder};
+use std::sync::{Arc, Mutex};
+
+#[derive(Default)]
+struct Collect(Mutex<Vec<PublishMetrics>>);
+
+impl Recorder for Collect {
+    fn record_publish(&self, metrics: &PublishMetrics) {
+        self.0.lock().unwrap().push(*metrics);
+    }
+}
+
+#[test]
+fn records_publishes() {
+    let recorder = Arc::new(Collect::default());
+    let (mut w, r) = left_right_map::new();
+    w.set_recorder(recorder.clone());
+
+    w.insert(1, "a").insert(2, "b");
+    w.publish();
+    let reader = r.clone();
+    w.remove(1);
+    w.publish();
+    drop(reader);
+
+    // shared writer keeps reporting to the same recorder
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+    w.insert(3, "c");
+    w.publish();
+
+    let published = recorder.0.lock().unwrap();
+    let summary: Vec<_> = published
+        .iter()
+        .map(|m| (m.operations, m.len, m.readers, m.epoch))
+        .collect();
+    // readers include the one owned by the writer
+    assert_eq!(summary, vec![(2, 2, 2, 1), (1, 1, 3, 2), (1, 2, 2, 3)]);
+}
+
+#[test]
+fn tracing_recorder() {
+    let (mut w, _r) = left_right_map::new();
+    w.set_recorder(Arc::new(TracingRecorder));
+    w.insert(1, 1);
+    w.publish();
+}
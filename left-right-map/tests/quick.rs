@@ -1,9 +1,12 @@
 #[macro_use(quickcheck)]
 extern crate quickcheck_macros;
 
-use std::collections::HashSet;
+use left_right_map::read::ReadHandle;
+use quickcheck::{Arbitrary, Gen};
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 
+#[allow(clippy::multiple_bound_locations)]
 fn set<'a, T: 'a, I>(iter: I) -> HashSet<T>
 where
     I: IntoIterator<Item = &'a T>,
@@ -49,3 +52,124 @@ fn insert_empty(insert: Vec<u8>, remove: Vec<u8>) -> bool {
     let elements = &set(&insert) - &set(&remove);
     r.len() == elements.len() && elements.iter().all(|k| r.get(k).is_some())
 }
+
+/// Keys are drawn from a small range, so operations often hit existing entries.
+const KEYS: u8 = 8;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Insert(u8, u32),
+    Update(u8, u32),
+    Remove(u8),
+    Modify(u8, u32),
+    Purge,
+    Publish,
+}
+
+impl Arbitrary for Op {
+    fn arbitrary(g: &mut Gen) -> Self {
+        let key = u8::arbitrary(g) % KEYS;
+        match g.choose(&[0, 1, 2, 3, 4, 5]).unwrap() {
+            0 => Op::Insert(key, u32::arbitrary(g)),
+            1 => Op::Update(key, u32::arbitrary(g)),
+            2 => Op::Remove(key),
+            3 => Op::Modify(key, u32::arbitrary(g)),
+            4 => Op::Purge,
+            _ => Op::Publish,
+        }
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        match *self {
+            Op::Insert(key, value) => Box::new(value.shrink().map(move |v| Op::Insert(key, v))),
+            Op::Update(key, value) => Box::new(value.shrink().map(move |v| Op::Update(key, v))),
+            Op::Modify(key, delta) => Box::new(delta.shrink().map(move |d| Op::Modify(key, d))),
+            _ => quickcheck::empty_shrinker(),
+        }
+    }
+}
+
+fn apply(model: &mut HashMap<u8, u32>, op: &Op) {
+    match *op {
+        Op::Insert(key, value) => {
+            model.insert(key, value);
+        }
+        Op::Update(key, value) => {
+            if let Some(v) = model.get_mut(&key) {
+                *v = value;
+            }
+        }
+        Op::Remove(key) => {
+            model.remove(&key);
+        }
+        Op::Modify(key, delta) => {
+            if let Some(v) = model.get_mut(&key) {
+                *v = v.wrapping_add(delta);
+            }
+        }
+        Op::Purge => model.clear(),
+        Op::Publish => {}
+    }
+}
+
+fn sees(reader: &ReadHandle<u8, u32>, published: Option<&HashMap<u8, u32>>) -> bool {
+    let Some(published) = published else {
+        // nothing is visible before the first publish
+        return reader.enter().is_none() && reader.is_empty();
+    };
+    let seen: HashMap<u8, u32> = reader.map_values(|&k, &v| (k, v)).into_iter().collect();
+    seen == *published
+        && reader.len() == published.len()
+        && (0..KEYS).all(|key| reader.get_cloned(&key) == published.get(&key).copied())
+}
+
+#[quickcheck]
+fn behaves_like_hashmap(ops: Vec<Op>) -> bool {
+    let (mut w, r) = left_right_map::new();
+    // enabling the view publishes the empty map
+    w.enable_view();
+    // readers created up front, from a clone and from a factory, all must agree
+    let mut readers = vec![r.clone(), r.factory().handle(), r];
+    let mut pending = HashMap::new();
+    let mut published = Some(HashMap::new());
+
+    for (i, op) in ops.iter().enumerate() {
+        match *op {
+            Op::Insert(key, value) => {
+                w.insert(key, value);
+            }
+            Op::Update(key, value) => {
+                w.update(key, value);
+            }
+            Op::Remove(key) => {
+                w.remove(key);
+            }
+            Op::Modify(key, delta) => {
+                w.modify(key, move |v: &mut u32| *v = v.wrapping_add(delta));
+            }
+            Op::Purge => {
+                w.purge();
+            }
+            Op::Publish => {
+                w.publish();
+                published = Some(pending.clone());
+            }
+        }
+        apply(&mut pending, op);
+        // writer reads its own writes right away
+        let view: HashMap<u8, u32> = w.view().unwrap().iter().map(|(&k, &v)| (k, v)).collect();
+        if view != pending {
+            return false;
+        }
+        if i % 4 == 0 {
+            // readers joining midway see the same state as the old ones
+            readers.push(readers[0].clone());
+        }
+        if !readers.iter().all(|r| sees(r, published.as_ref())) {
+            return false;
+        }
+    }
+
+    w.publish();
+    readers.iter().all(|r| sees(r, Some(&pending)))
+}
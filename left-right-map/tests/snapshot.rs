@@ -0,0 +1,97 @@
+#![cfg(feature = "serde")]
+
+use left_right_map::snapshot::{SnapshotError, SNAPSHOT_VERSION};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn snapshot_roundtrip() {
+    let (mut w, _r) = left_right_map::new();
+    w.insert("a".to_string(), 1u64);
+    w.insert("b".to_string(), 2u64);
+    w.publish();
+    // pending operations are not part of the snapshot
+    w.insert("c".to_string(), 3u64);
+
+    let mut buf = Vec::new();
+    w.snapshot_to(&mut buf).unwrap();
+
+    let (mut w, r) = left_right_map::from_snapshot::<String, u64, _>(buf.as_slice()).unwrap();
+    // ready without publish
+    assert_eq!(r.len(), 2);
+    assert_eq!(*r.get("a").unwrap(), 1);
+    assert_eq!(*r.get("b").unwrap(), 2);
+    assert!(!r.contains_key("c"));
+
+    w.insert("c".to_string(), 3);
+    w.publish();
+    w.publish();
+    assert_eq!(r.len(), 3);
+}
+
+#[test]
+fn snapshot_keeps_deadlines() {
+    let (mut w, _r) = left_right_map::new();
+    w.insert_with_ttl(1u32, 1u32, Duration::from_millis(50));
+    w.insert_with_ttl(2, 2, Duration::ZERO);
+    w.insert(3, 3);
+    w.publish();
+
+    let mut buf = Vec::new();
+    w.snapshot_to(&mut buf).unwrap();
+
+    let (_w, r) = left_right_map::from_snapshot::<u32, u32, _>(buf.as_slice()).unwrap();
+    // expired entry is left out of the snapshot
+    assert_eq!(r.len(), 2);
+    assert_eq!(*r.get(&1).unwrap(), 1);
+    assert!(r.get(&2).is_none());
+
+    thread::sleep(Duration::from_millis(60));
+    assert!(r.get(&1).is_none());
+    assert_eq!(*r.get(&3).unwrap(), 3);
+}
+
+#[test]
+fn snapshot_shared_writer() {
+    let (w, _r) = left_right_map::new();
+    let mut w = left_right_map::write::SharedWriteHandle::from(w);
+    w.insert(1u32, vec![1u8, 2, 3]);
+    w.publish();
+
+    let mut buf = Vec::new();
+    w.snapshot_to(&mut buf).unwrap();
+
+    let (_w, r) = left_right_map::from_snapshot::<u32, Vec<u8>, _>(buf.as_slice()).unwrap();
+    assert_eq!(*r.get(&1).unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn snapshot_rejects_incompatible() {
+    let (mut w, _r) = left_right_map::new();
+    w.insert(1u32, 1u32);
+    w.publish();
+    let mut buf = Vec::new();
+    w.snapshot_to(&mut buf).unwrap();
+
+    let mut other_version = buf.clone();
+    other_version[4..8].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+    assert!(matches!(
+        left_right_map::from_snapshot::<u32, u32, _>(other_version.as_slice()),
+        Err(SnapshotError::UnsupportedVersion(v)) if v == SNAPSHOT_VERSION + 1
+    ));
+
+    assert!(matches!(
+        left_right_map::from_snapshot::<u32, u32, _>(&b"not a snapshot"[..]),
+        Err(SnapshotError::InvalidHeader)
+    ));
+
+    assert!(matches!(
+        left_right_map::from_snapshot::<u32, u32, _>(&buf[..6]),
+        Err(SnapshotError::Io(_))
+    ));
+
+    assert!(matches!(
+        left_right_map::from_snapshot::<String, u32, _>(buf.as_slice()),
+        Err(SnapshotError::Decode(_))
+    ));
+}
@@ -0,0 +1,172 @@
+#![cfg(feature = "serde")]
+
+use left_right_map::wal::{self, Registry, WalError};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn log_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+        "left-right-map-{}-{}.wal",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn registry() -> Registry<u64> {
+    Registry::new()
+        .with_modifier("increment", |v| *v += 1)
+        .with_predicate("is_zero", |v| *v == 0)
+}
+
+#[test]
+fn replay() {
+    let path = log_path("replay");
+    {
+        let (mut w, r) = wal::open::<String, u64, _>(&path, registry()).unwrap();
+        w.insert("a".to_string(), 1).unwrap();
+        w.insert("b".to_string(), 0).unwrap();
+        w.modify_named("a".to_string(), "increment").unwrap();
+        w.publish().unwrap();
+        assert_eq!(*r.get("a").unwrap(), 2);
+
+        // never published, still has to survive the restart
+        w.upsert_named("c".to_string(), 10, "increment").unwrap();
+        w.remove_if_named("b".to_string(), "is_zero").unwrap();
+    }
+
+    let (_w, r) = wal::open::<String, u64, _>(&path, registry()).unwrap();
+    assert_eq!(r.len(), 2);
+    assert_eq!(*r.get("a").unwrap(), 2);
+    assert_eq!(*r.get("c").unwrap(), 10);
+    assert!(!r.contains_key("b"));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn replay_bulk_and_ttl() {
+    let path = log_path("bulk");
+    {
+        let (mut w, _r) = wal::open::<u32, u64, _>(&path, registry()).unwrap();
+        w.replace_all(HashMap::from([(1, 0), (2, 2)])).unwrap();
+        w.extend([(3, 0), (4, 4)]).unwrap();
+        w.retain_named("is_zero").unwrap();
+        w.update_or_insert(5, 5).unwrap();
+        w.insert_with_ttl(6, 6, Duration::from_secs(60)).unwrap();
+        w.insert_with_ttl(7, 7, Duration::ZERO).unwrap();
+        w.sweep().unwrap();
+    }
+
+    let (_w, r) = wal::open::<u32, u64, _>(&path, registry()).unwrap();
+    let mut keys: Vec<_> = r.enter().unwrap().keys().copied().collect();
+    keys.sort();
+    assert_eq!(keys, [1, 3, 5, 6]);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn unknown_names() {
+    let path = log_path("unknown");
+    let (mut w, _r) = wal::open::<u32, u64, _>(&path, registry()).unwrap();
+    assert!(matches!(
+        w.modify_named(1, "decrement"),
+        Err(WalError::UnknownModifier(name)) if name == "decrement"
+    ));
+    assert!(matches!(
+        w.remove_if_named(1, "is_one"),
+        Err(WalError::UnknownPredicate(name)) if name == "is_one"
+    ));
+    assert!(!w.has_pending());
+    w.modify_named(1, "increment").unwrap();
+    drop(w);
+
+    // replaying without the modifier fails as well
+    assert!(matches!(
+        wal::open::<u32, u64, _>(&path, Registry::new()),
+        Err(WalError::UnknownModifier(name)) if name == "increment"
+    ));
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn checkpoint() {
+    let path = log_path("checkpoint");
+    let mut snapshot = Vec::new();
+    {
+        let (mut w, _r) = wal::open::<u32, u64, _>(&path, registry()).unwrap();
+        w.insert(1, 1).unwrap();
+        w.modify_named(1, "increment").unwrap();
+        w.checkpoint(&mut snapshot).unwrap();
+        w.modify_named(1, "increment").unwrap();
+        w.insert(2, 0).unwrap();
+    }
+
+    let (mut w, r) =
+        wal::open_with_snapshot::<u32, u64, _, _>(snapshot.as_slice(), &path, registry()).unwrap();
+    assert_eq!(*r.get(&1).unwrap(), 3);
+    assert_eq!(*r.get(&2).unwrap(), 0);
+
+    // operations logged after restore continue the sequence
+    w.modify_named(2, "increment").unwrap();
+    drop(w);
+    let (_w, r) =
+        wal::open_with_snapshot::<u32, u64, _, _>(snapshot.as_slice(), &path, registry()).unwrap();
+    assert_eq!(*r.get(&1).unwrap(), 3);
+    assert_eq!(*r.get(&2).unwrap(), 1);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn snapshot_skips_logged_operations() {
+    // process died after the snapshot was written, but before the log got truncated
+    let path = log_path("skip");
+    let mut snapshot = Vec::new();
+    let log = {
+        let (mut w, _r) = wal::open::<u32, u64, _>(&path, registry()).unwrap();
+        w.insert(1, 1).unwrap();
+        w.modify_named(1, "increment").unwrap();
+        w.publish().unwrap();
+        let log = std::fs::read(&path).unwrap();
+        w.checkpoint(&mut snapshot).unwrap();
+        log
+    };
+    std::fs::write(&path, log).unwrap();
+
+    let (_w, r) =
+        wal::open_with_snapshot::<u32, u64, _, _>(snapshot.as_slice(), &path, registry()).unwrap();
+    assert_eq!(*r.get(&1).unwrap(), 2);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn torn_entry() {
+    let path = log_path("torn");
+    {
+        let (mut w, _r) = wal::open::<u32, u64, _>(&path, registry()).unwrap();
+        w.insert(1, 1).unwrap();
+    }
+    // half written entry at the end of the log
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[0xa2, 0x63]).unwrap();
+    drop(file);
+
+    {
+        let (mut w, r) = wal::open::<u32, u64, _>(&path, registry()).unwrap();
+        assert_eq!(*r.get(&1).unwrap(), 1);
+        w.insert(2, 2).unwrap();
+    }
+
+    let (_w, r) = wal::open::<u32, u64, _>(&path, registry()).unwrap();
+    assert_eq!(r.len(), 2);
+
+    std::fs::remove_file(&path).unwrap();
+}